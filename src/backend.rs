@@ -0,0 +1,179 @@
+use crate::config::Config;
+use crate::homebrew::{Homebrew, HomebrewPackageType};
+use crate::node::NodeManager;
+use crate::output;
+use crate::version;
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Summary of a package as reported by a backend's metadata source.
+#[derive(Debug, Clone)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+}
+
+/// A package currently installed through a backend.
+#[derive(Debug, Clone)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// An installed package with a newer version available.
+#[derive(Debug, Clone)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub current: String,
+    pub latest: String,
+}
+
+/// A source of packages bert can manage (Homebrew formulae, casks, node globals, ...).
+#[async_trait]
+pub trait PackageBackend: Send + Sync {
+    /// Short identifier used to select the backend, e.g. "formula" or "node".
+    fn name(&self) -> &'static str;
+
+    /// Heading used when listing installed packages.
+    fn display_name(&self) -> &'static str;
+
+    /// The tool packages are installed with, e.g. "homebrew" or "pnpm".
+    fn source(&self) -> &'static str;
+
+    /// Search for packages matching `query`, returning at most `limit` results.
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<PackageInfo>>;
+
+    /// Print search results for humans. Backends can override this to show richer details.
+    async fn display_search_results(&self, query: &str, results: &[PackageInfo]) -> Result<()> {
+        output::print_search_table(query, results);
+        Ok(())
+    }
+
+    /// Look up metadata for a single package by exact name.
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>>;
+
+    /// Every version of a package that can be installed by passing it to `install`.
+    async fn versions(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .info(name)
+            .await?
+            .into_iter()
+            .map(|info| info.version)
+            .collect())
+    }
+
+    async fn install(&self, name: &str, version: Option<&str>) -> Result<()>;
+
+    /// Install without progress output of its own, for installs that run alongside others
+    /// and have their status reported by the caller.
+    async fn install_quietly(&self, name: &str, version: Option<&str>) -> Result<()> {
+        self.install(name, version).await
+    }
+
+    /// Whether several installs through this backend can safely run at the same time.
+    fn supports_parallel_install(&self) -> bool {
+        false
+    }
+
+    async fn uninstall(&self, name: &str) -> Result<()>;
+
+    /// Replace the installed version of `name` with the older `version`, for undoing an
+    /// update. Backends whose `install` can't downgrade in place override this.
+    async fn revert(&self, name: &str, version: &str) -> Result<()> {
+        self.install(name, Some(version)).await
+    }
+
+    /// Orders `packages` so that each comes before the ones it depends on, the order
+    /// they can be uninstalled in.
+    async fn uninstall_order(&self, packages: &[String]) -> Result<Vec<String>> {
+        Ok(packages.to_vec())
+    }
+
+    /// Refresh the backend's own package metadata, e.g. `brew update`.
+    async fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Mirror a bert pin to the backend, for backends that have pins of their own.
+    async fn set_pinned(&self, _name: &str, _pinned: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// Upgrade the given packages, or everything installed if `packages` is empty.
+    async fn upgrade(&self, packages: &[String]) -> Result<()>;
+
+    async fn list(&self) -> Result<Vec<InstalledPackage>>;
+
+    /// Installed packages whose latest published version is newer than the installed one.
+    async fn outdated(&self) -> Result<Vec<OutdatedPackage>> {
+        let mut outdated = Vec::new();
+        for package in self.list().await? {
+            if let Some(info) = self.info(&package.name).await? {
+                if version::is_newer(&info.version, &package.version) {
+                    outdated.push(OutdatedPackage {
+                        name: package.name,
+                        current: package.version,
+                        latest: info.version,
+                    });
+                }
+            }
+        }
+        Ok(outdated)
+    }
+}
+
+/// Returns the name of the backend selected by the `--cask` / `--node` flags.
+pub fn backend_name(is_cask: bool, is_node: bool) -> &'static str {
+    if is_node {
+        "node"
+    } else if is_cask {
+        "cask"
+    } else {
+        "formula"
+    }
+}
+
+pub struct Registry {
+    backends: Vec<Box<dyn PackageBackend>>,
+}
+
+impl Registry {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut registry = Self {
+            backends: Vec::new(),
+        };
+
+        registry.register(Box::new(Homebrew::new(HomebrewPackageType::Formula)));
+        registry.register(Box::new(Homebrew::new(HomebrewPackageType::Cask)));
+        registry.register(Box::new(NodeManager::new(
+            config.get_node_package_manager()?,
+        )));
+
+        Ok(registry)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::new(&Config::load()?)
+    }
+
+    pub fn register(&mut self, backend: Box<dyn PackageBackend>) {
+        self.backends.push(backend);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &dyn PackageBackend> {
+        self.backends.iter().map(|backend| backend.as_ref())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&dyn PackageBackend> {
+        self.backends
+            .iter()
+            .find(|backend| backend.name() == name)
+            .map(|backend| backend.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("Unknown package backend: {}", name))
+    }
+
+    pub fn select(&self, is_cask: bool, is_node: bool) -> Result<&dyn PackageBackend> {
+        self.get(backend_name(is_cask, is_node))
+    }
+}
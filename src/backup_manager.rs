@@ -1,84 +1,107 @@
+use crate::backend::{PackageBackend, Registry};
+use crate::config::Config;
+use crate::history::{self, Action};
+use crate::homebrew::{brew, HomebrewPackageType};
+use crate::node::{NodeManager, NodePackageManager};
+use crate::spec::PackageSpec;
+use crate::{dry_run, formula_index, homebrew, lockfile, node, restore_jobs};
 use anyhow::Result;
-use chrono::Local;
+use chrono::{DateTime, Local};
 use colored::*;
 use dirs::home_dir;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
+
+/// Schema of the backups this version of bert writes. Bump it and add a step to
+/// `migrate` whenever `BackupFile` changes shape.
+const SCHEMA_VERSION: u32 = 3;
 
 #[derive(Serialize, Deserialize)]
 struct BackupFile {
+    schema_version: u32,
     created_at: String,
     formulas: Vec<FormulaBackup>,
     casks: Vec<CaskBackup>,
     taps: Vec<String>,
+    /// `None` for backups made before Node.js packages were backed up
+    node: Option<NodeBackup>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct FormulaBackup {
     name: String,
     version: String,
+    /// Options the formula was installed with
     options: Vec<String>,
+    installed_as_dependency: bool,
+    installed_on_request: bool,
+    poured_from_bottle: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+impl FormulaBackup {
+    /// Formulae the user asked for, as opposed to ones pulled in by another formula.
+    /// Only these are installed on restore, dependencies resolve themselves.
+    fn is_top_level(&self) -> bool {
+        self.installed_on_request || !self.installed_as_dependency
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct CaskBackup {
     name: String,
     version: String,
 }
 
+#[derive(Serialize, Deserialize)]
+struct NodeBackup {
+    /// Package manager the packages were installed with ("npm", "yarn", "pnpm" or "bun")
+    package_manager: String,
+    packages: Vec<NodePackageBackup>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct NodePackageBackup {
+    name: String,
+    version: String,
+}
+
+/// Global packages that come with Node.js itself rather than being installed by the user
+const BUNDLED_NODE_PACKAGES: [&str; 2] = ["npm", "corepack"];
+
 pub async fn create_backup(path: Option<&str>) -> Result<()> {
     println!("Creating backup of Homebrew packages 🐕");
 
     // Get all taps
-    let taps_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-        .args(["tap"])
-        .output()?;
+    let taps_output = Command::new(brew()).args(["tap"]).output()?;
     let taps = String::from_utf8_lossy(&taps_output.stdout)
         .lines()
         .map(String::from)
         .collect::<Vec<_>>();
 
-    // Get installed formulas
-    let formulas_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-        .args(["list", "--formula", "--versions"])
-        .output()?;
-
-    let formulas = String::from_utf8_lossy(&formulas_output.stdout)
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            let name = parts[0];
-            let version = parts.get(1).unwrap_or(&"").to_string();
-
-            // Get install options if any
-            let options_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-                .args(["info", "--json=v2", name])
-                .output()
-                .unwrap();
-
-            let options = if options_output.status.success() {
-                String::from_utf8_lossy(&options_output.stdout)
-                    .lines()
-                    .filter(|line| line.contains("--"))
-                    .map(String::from)
-                    .collect()
-            } else {
-                Vec::new()
-            };
-
-            FormulaBackup {
-                name: name.to_string(),
-                version,
-                options,
-            }
+    // Get installed formulas, with how and why each was installed
+    let formulas = homebrew::brew_info(&["--installed"])?
+        .formulae
+        .into_iter()
+        .filter_map(|formula| {
+            let keg = formula.installed.into_iter().last()?;
+            Some(FormulaBackup {
+                name: formula.name,
+                version: keg.version,
+                options: keg.used_options,
+                installed_as_dependency: keg.installed_as_dependency,
+                installed_on_request: keg.installed_on_request,
+                poured_from_bottle: keg.poured_from_bottle,
+            })
         })
         .collect::<Vec<_>>();
 
     // Get installed casks
-    let casks_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
+    let casks_output = Command::new(brew())
         .args(["list", "--cask", "--versions"])
         .output()?;
 
@@ -94,11 +117,30 @@ pub async fn create_backup(path: Option<&str>) -> Result<()> {
         })
         .collect::<Vec<_>>();
 
+    // Get global Node.js packages, if a package manager is installed
+    let package_manager = Config::load()?.get_node_package_manager()?;
+    let node_packages = NodeManager::new(package_manager.clone())
+        .list_packages()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|package| !BUNDLED_NODE_PACKAGES.contains(&package.name.as_str()))
+        .map(|package| NodePackageBackup {
+            name: package.name,
+            version: package.version,
+        })
+        .collect::<Vec<_>>();
+
     let backup = BackupFile {
+        schema_version: SCHEMA_VERSION,
         created_at: Local::now().to_rfc3339(),
         formulas,
         casks,
         taps,
+        node: Some(NodeBackup {
+            package_manager: package_manager.command().to_string(),
+            packages: node_packages,
+        }),
     };
 
     // Determine backup path
@@ -106,17 +148,46 @@ pub async fn create_backup(path: Option<&str>) -> Result<()> {
     let backup_json = serde_json::to_string_pretty(&backup)?;
     fs::write(&backup_path, backup_json)?;
 
+    let lock_path = lockfile::path_for(&backup_path);
+    lockfile::save(&lockfile::generate(&backup.taps).await?, &lock_path)?;
+
     println!("{}", "Backup created successfully!".green());
     println!("Backup location: {}", backup_path.display());
+    println!("Lockfile location: {}", lock_path.display());
     println!("Summary:");
     println!("  Taps: {}", backup.taps.len());
-    println!("  Formulas: {}", backup.formulas.len());
+    let dependencies = backup
+        .formulas
+        .iter()
+        .filter(|formula| !formula.is_top_level())
+        .count();
+    println!(
+        "  Formulas: {} ({} installed as dependencies)",
+        backup.formulas.len(),
+        dependencies
+    );
     println!("  Casks: {}", backup.casks.len());
+    if let Some(node) = &backup.node {
+        println!(
+            "  Node.js packages: {} ({})",
+            node.packages.len(),
+            node.package_manager
+        );
+    }
 
     Ok(())
 }
 
-pub async fn restore_backup(path: Option<&str>) -> Result<()> {
+pub async fn restore_backup(
+    path: Option<&str>,
+    locked: bool,
+    resume: bool,
+    jobs: Option<usize>,
+) -> Result<()> {
+    if locked && resume {
+        anyhow::bail!("--resume can't be combined with --locked, which always installs everything");
+    }
+
     let backup_path = if let Some(p) = path {
         PathBuf::from(p)
     } else {
@@ -126,71 +197,554 @@ pub async fn restore_backup(path: Option<&str>) -> Result<()> {
     println!("Restoring Homebrew packages from backup 🐕");
     println!("Reading backup from: {}", backup_path.display());
 
-    let backup_content = fs::read_to_string(&backup_path)?;
-    let backup: BackupFile = serde_json::from_str(&backup_content)?;
+    let backup = load_backup(&backup_path)?;
 
     println!("Backup created at: {}", backup.created_at);
+    let node_packages = backup.node.as_ref().map_or(0, |node| node.packages.len());
     println!(
-        "\nRestoring {} taps, {} formulas, and {} casks 🐕",
+        "\nRestoring {} taps, {} formulas, {} casks and {} Node.js packages 🐕",
         backup.taps.len(),
-        backup.formulas.len(),
-        backup.casks.len()
+        backup.formulas.iter().filter(|f| f.is_top_level()).count(),
+        backup.casks.len(),
+        node_packages
     );
 
-    // First restore taps
-    println!("\n{}:", "Restoring taps".cyan());
-    for tap in &backup.taps {
-        print!("  {:<40}", tap);
-        let status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["tap", tap])
-            .status()?;
-
-        if status.success() {
-            println!("{}", "✓".green());
-        } else {
-            println!("{}", "✗".red());
+    let jobs = match jobs {
+        Some(jobs) => jobs,
+        None => Config::load()?.restore_jobs,
+    };
+
+    let registry = Registry::load()?;
+    if locked {
+        let lock_path = lockfile::path_for(&backup_path);
+        if !lock_path.exists() {
+            anyhow::bail!(
+                "No lockfile found at {}, create a new backup to get one",
+                lock_path.display()
+            );
         }
+        let lock = lockfile::load(&lock_path)?;
+        println!(
+            "Installing the exact versions locked in {}",
+            lock_path.display()
+        );
+
+        history::track(
+            Action::Restore,
+            &[
+                registry.get("formula")?,
+                registry.get("cask")?,
+                registry.get("node")?,
+            ],
+            lockfile::restore(&lock),
+        )
+        .await?;
+    } else {
+        history::track(
+            Action::Restore,
+            &[
+                registry.get("formula")?,
+                registry.get("cask")?,
+                registry.get("node")?,
+            ],
+            restore_packages(&backup, &backup_path, resume, jobs),
+        )
+        .await?;
+    }
+
+    if dry_run::is_enabled() {
+        println!("\n{}", "Dry run completed, nothing was changed.".yellow());
+        return Ok(());
     }
 
-    // Then restore formulas
-    println!("\n{}:", "Restoring formulas".cyan());
-    for formula in &backup.formulas {
-        print!("  {:<40}", formula.name);
+    println!("\n{}", "Restore completed!".green());
+    Ok(())
+}
 
-        let mut args = vec!["install"];
-        args.push(&formula.name);
-        args.extend(formula.options.iter().map(|s| s.as_str()));
+async fn restore_packages(
+    backup: &BackupFile,
+    backup_path: &Path,
+    resume: bool,
+    jobs: usize,
+) -> Result<()> {
+    let journal = Arc::new(restore_jobs::Journal::open(
+        backup_path.with_extension("progress"),
+        resume,
+    )?);
+    let mut failures = Vec::new();
+
+    // First restore taps, one at a time since formulas and casks may come from them
+    println!("\n{}:", "Restoring taps".cyan());
+    let tap_jobs = backup
+        .taps
+        .iter()
+        .map(|tap| brew_job("tap", tap, vec!["tap".to_string(), tap.clone()]))
+        .collect();
+    failures.extend(restore_jobs::run(tap_jobs, 1, &journal).await);
+
+    // Then formulas and casks side by side. Installs that share a dependency would wait
+    // on each other's Homebrew locks, so those run one after another
+    println!("\n{}:", "Restoring formulas and casks".cyan());
+    let formulas = backup
+        .formulas
+        .iter()
+        .filter(|formula| formula.is_top_level())
+        .collect::<Vec<_>>();
+    let formula_deps = brew_dependencies("--formula", formulas.iter().map(|f| f.name.as_str()));
+    let cask_deps = brew_dependencies("--cask", backup.casks.iter().map(|c| c.name.as_str()));
+
+    let formula_jobs = formulas.iter().enumerate().map(|(index, formula)| {
+        let mut args = vec!["install".to_string(), formula.name.clone()];
+        args.extend(formula.options.iter().cloned());
+        let mut needs = dependencies_at(&formula_deps, index);
+        needs.insert(formula.name.clone());
+        (brew_job("formula", &formula.name, args), needs)
+    });
+    let cask_jobs = backup.casks.iter().enumerate().map(|(index, cask)| {
+        let args = vec![
+            "install".to_string(),
+            "--cask".to_string(),
+            cask.name.clone(),
+        ];
+        let needs = dependencies_at(&cask_deps, index);
+        (brew_job("cask", &cask.name, args), needs)
+    });
+    let chains = restore_jobs::chain_by_shared(formula_jobs.chain(cask_jobs).collect());
+    failures.extend(restore_jobs::run_chains(chains, jobs, &journal).await);
+
+    // Node.js packages go last, their package manager may have just been restored above
+    if let Some(node) = backup
+        .node
+        .as_ref()
+        .filter(|node| !node.packages.is_empty())
+    {
+        println!("\n{}:", "Restoring Node.js packages".cyan());
+        let package_manager = NodePackageManager::from_str(&node.package_manager)?;
+        let manager = NodeManager::new(package_manager.clone());
+        let node_jobs = node.packages.iter().map(|package| {
+            let spec = PackageSpec {
+                name: package.name.clone(),
+                version: Some(package.version.clone()),
+            };
+            let (name, version, package_manager) = (
+                package.name.clone(),
+                package.version.clone(),
+                package_manager.clone(),
+            );
+
+            restore_jobs::Job {
+                key: format!("node:{}", package.name),
+                label: spec.to_string(),
+                work: restore_jobs::Work::Task(Box::pin(async move {
+                    NodeManager::new(package_manager)
+                        .install_quietly(&name, Some(&version))
+                        .await
+                })),
+            }
+        });
 
-        let status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(&args)
-            .status()?;
+        let bootstrap = match manager.is_node_installed() {
+            true => Ok(()),
+            false => {
+                println!("Node.js is required. Installing Node.js first...");
+                homebrew::install_formula_version("node", None, false).await
+            }
+        };
+
+        match bootstrap {
+            Ok(()) => {
+                // Only npm keeps each global package separate enough to install several at once
+                let limit = match manager.supports_parallel_install() {
+                    true => jobs,
+                    false => 1,
+                };
+                failures.extend(restore_jobs::run(node_jobs.collect(), limit, &journal).await);
+            }
+            Err(e) => {
+                println!("  {:<40} {} {}", "node", "✗".red(), e);
+                failures.push(restore_jobs::Failure {
+                    key: "formula:node".to_string(),
+                    label: "node".to_string(),
+                    reason: e.to_string(),
+                });
+                failures.extend(node_jobs.map(|job| restore_jobs::Failure {
+                    key: job.key,
+                    label: job.label,
+                    reason: "Node.js could not be installed".to_string(),
+                }));
+            }
+        }
+    }
 
-        if status.success() {
-            println!("{}", "✓".green());
-        } else {
-            println!("{}", "✗".red());
+    let retry_path = retry_path_for(backup_path);
+    if failures.is_empty() {
+        if !dry_run::is_enabled() {
+            Arc::into_inner(journal)
+                .expect("restore jobs have finished")
+                .finish()?;
+            if retry_path.exists() {
+                fs::remove_file(&retry_path)?;
+            }
         }
+        return Ok(());
     }
 
-    // Finally restore casks
-    println!("\n{}:", "Restoring casks".cyan());
-    for cask in &backup.casks {
-        print!("  {:<40}", cask.name);
-        let status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["install", "--cask", &cask.name])
-            .status()?;
+    println!(
+        "\n{}",
+        format!("{} entries failed to restore:", failures.len()).red()
+    );
+    for failure in &failures {
+        println!("  {:<40} {}", failure.label, failure.reason);
+    }
 
-        if status.success() {
-            println!("{}", "✓".green());
-        } else {
-            println!("{}", "✗".red());
+    let failed = failures
+        .iter()
+        .map(|failure| failure.key.as_str())
+        .collect::<HashSet<_>>();
+    let retry = retry_backup(backup, &failed);
+    fs::write(&retry_path, serde_json::to_string_pretty(&retry)?)?;
+
+    println!(
+        "\nThe failed entries were saved to {}",
+        retry_path.display()
+    );
+    println!(
+        "Retry them with `bert restore -i {}`, or continue this restore with `bert restore -i {} --resume`",
+        retry_path.display(),
+        backup_path.display()
+    );
+    anyhow::bail!("{} entries failed to restore", failures.len())
+}
+
+/// Where the entries that failed to restore from `backup_path` are saved. Retrying from
+/// that file writes back to the same path instead of stacking up `.retry` suffixes.
+fn retry_path_for(backup_path: &Path) -> PathBuf {
+    let stem = backup_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let stem = stem.strip_suffix(".retry").unwrap_or(&stem);
+    backup_path.with_file_name(format!("{}.retry.json", stem))
+}
+
+fn brew_job(kind: &str, name: &str, args: Vec<String>) -> restore_jobs::Job {
+    restore_jobs::Job {
+        key: format!("{}:{}", kind, name),
+        label: name.to_string(),
+        work: restore_jobs::Work::Command {
+            program: brew().to_string(),
+            args,
+        },
+    }
+}
+
+/// Recursive dependencies of each of `names`, in the same order, from
+/// `brew deps --for-each`. `None` if brew couldn't tell.
+fn brew_dependencies<'a>(
+    kind: &str,
+    names: impl Iterator<Item = &'a str>,
+) -> Option<Vec<HashSet<String>>> {
+    let names = names.collect::<Vec<_>>();
+    if names.is_empty() {
+        return Some(Vec::new());
+    }
+
+    let output = Command::new(brew())
+        .args(["deps", kind, "--for-each"])
+        .args(&names)
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    // One `name: dep dep ...` line per name, in the order they were given
+    let deps = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(_, deps)| deps.split_whitespace().map(String::from).collect())
+        .collect::<Vec<HashSet<String>>>();
+    (deps.len() == names.len()).then_some(deps)
+}
+
+/// The dependencies of the `index`th name, or a stand-in that every job shares when
+/// they're unknown, so that those jobs run one at a time.
+fn dependencies_at(deps: &Option<Vec<HashSet<String>>>, index: usize) -> HashSet<String> {
+    match deps {
+        Some(deps) => deps[index].clone(),
+        None => HashSet::from(["<unknown>".to_string()]),
+    }
+}
+
+/// A backup of just the entries whose journal keys are in `failed`.
+fn retry_backup(backup: &BackupFile, failed: &HashSet<&str>) -> BackupFile {
+    let is_failed = |kind: &str, name: &str| failed.contains(format!("{}:{}", kind, name).as_str());
+
+    BackupFile {
+        schema_version: SCHEMA_VERSION,
+        created_at: backup.created_at.clone(),
+        taps: backup
+            .taps
+            .iter()
+            .filter(|tap| is_failed("tap", tap))
+            .cloned()
+            .collect(),
+        formulas: backup
+            .formulas
+            .iter()
+            .filter(|formula| is_failed("formula", &formula.name))
+            .cloned()
+            .collect(),
+        casks: backup
+            .casks
+            .iter()
+            .filter(|cask| is_failed("cask", &cask.name))
+            .cloned()
+            .collect(),
+        node: backup.node.as_ref().map(|node| NodeBackup {
+            package_manager: node.package_manager.clone(),
+            packages: node
+                .packages
+                .iter()
+                .filter(|package| is_failed("node", &package.name))
+                .cloned()
+                .collect(),
+        }),
+    }
+}
+
+/// Reads a backup, upgrading it from older schema versions first.
+fn load_backup(path: &Path) -> Result<BackupFile> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read backup {}: {}", path.display(), e))?;
+    let mut value: Value = serde_json::from_str(&content)?;
+    let version = schema_version(&value)?;
+    migrate(&mut value, version)?;
+
+    serde_path_to_error::deserialize(value)
+        .map_err(|e| anyhow::anyhow!("Invalid backup {}: {}", path.display(), e))
+}
+
+fn schema_version(value: &Value) -> Result<u32> {
+    let version = match value.get("schema_version") {
+        // Backups from before the schema was versioned
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|&version| version >= 1)
+            .ok_or_else(|| anyhow::anyhow!("schema_version must be a positive integer"))?,
+    };
+
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "Backup uses schema version {}, but this bert only understands up to {}. Please update bert",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(version)
+}
+
+/// Upgrades backup JSON from schema `version` to `SCHEMA_VERSION`, one version at a time.
+fn migrate(value: &mut Value, version: u32) -> Result<()> {
+    let backup = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("A backup must be a JSON object"))?;
+
+    for from in version..SCHEMA_VERSION {
+        match from {
+            // 1 -> 2: global Node.js packages are backed up
+            1 => {
+                backup.entry("node").or_insert(Value::Null);
+            }
+            // 2 -> 3: formulas record how and why they were installed. Older backups
+            // didn't know, so every formula counts as requested. Their options were
+            // scraped from `brew info` output and never real options
+            2 => {
+                let formulas = backup
+                    .get_mut("formulas")
+                    .and_then(Value::as_array_mut)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_object_mut);
+                for formula in formulas {
+                    formula.insert("options".to_string(), Value::Array(Vec::new()));
+                    formula.insert("installed_as_dependency".to_string(), false.into());
+                    formula.insert("installed_on_request".to_string(), true.into());
+                    formula.insert("poured_from_bottle".to_string(), true.into());
+                }
+            }
+            _ => anyhow::bail!("No migration from backup schema version {}", from),
         }
     }
 
-    println!("\n{}", "Restore completed!".green());
+    backup.insert("schema_version".to_string(), SCHEMA_VERSION.into());
     Ok(())
 }
 
+/// Checks a backup for schema problems and for packages that can't be found, without
+/// installing anything.
+pub async fn validate_backup(path: &str) -> Result<()> {
+    println!("Validating backup {} 🐕", path);
+
+    let content = fs::read_to_string(path)?;
+    let mut value: Value = serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("{} is not valid JSON: {}", path, e))?;
+    let version = schema_version(&value)?;
+    println!("  Schema version: {}", version);
+    if version < SCHEMA_VERSION {
+        println!(
+            "  {}",
+            format!(
+                "Will be upgraded to schema version {} on restore",
+                SCHEMA_VERSION
+            )
+            .yellow()
+        );
+    }
+
+    migrate(&mut value, version)?;
+    let backup: BackupFile = serde_path_to_error::deserialize(value)
+        .map_err(|e| anyhow::anyhow!("Schema problem at {}", e))?;
+
+    let mut problems = schema_problems(&backup);
+    problems.extend(unknown_packages(&backup).await);
+
+    if problems.is_empty() {
+        println!("{}", "Backup is valid!".green());
+        return Ok(());
+    }
+
+    println!("\n{}:", "Problems".red());
+    for problem in &problems {
+        println!("  {} {}", "✗".red(), problem);
+    }
+    anyhow::bail!("Found {} problems in {}", problems.len(), path)
+}
+
+/// Problems serde can't catch: bad values and duplicate entries.
+fn schema_problems(backup: &BackupFile) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if DateTime::parse_from_rfc3339(&backup.created_at).is_err() {
+        problems.push(format!(
+            "created_at: '{}' is not an RFC 3339 timestamp",
+            backup.created_at
+        ));
+    }
+
+    for tap in &backup.taps {
+        if tap.split('/').count() != 2 || tap.split('/').any(str::is_empty) {
+            problems.push(format!("taps: '{}' is not a user/repo tap name", tap));
+        }
+    }
+
+    let mut check_names = |section: &str, names: Vec<&str>| {
+        let mut seen = HashSet::new();
+        for (index, name) in names.into_iter().enumerate() {
+            if name.trim().is_empty() {
+                problems.push(format!("{}[{}]: empty name", section, index));
+            } else if !seen.insert(name) {
+                problems.push(format!(
+                    "{}[{}]: duplicate entry for {}",
+                    section, index, name
+                ));
+            }
+        }
+    };
+    check_names(
+        "formulas",
+        backup.formulas.iter().map(|f| f.name.as_str()).collect(),
+    );
+    check_names(
+        "casks",
+        backup.casks.iter().map(|c| c.name.as_str()).collect(),
+    );
+    if let Some(node) = &backup.node {
+        check_names(
+            "node.packages",
+            node.packages.iter().map(|p| p.name.as_str()).collect(),
+        );
+        if let Err(e) = NodePackageManager::from_str(&node.package_manager) {
+            problems.push(format!("node.package_manager: {}", e));
+        }
+    }
+
+    problems
+}
+
+/// Packages that no longer exist upstream. Packages that can't be checked, e.g. while
+/// offline, are reported as warnings rather than problems.
+async fn unknown_packages(backup: &BackupFile) -> Vec<String> {
+    let mut problems = Vec::new();
+    let third_party_taps = backup
+        .taps
+        .iter()
+        .filter(|tap| !tap.starts_with("homebrew/"))
+        .count();
+
+    for (package_type, section, names) in [
+        (
+            HomebrewPackageType::Formula,
+            "formula",
+            backup.formulas.iter().map(|f| &f.name).collect::<Vec<_>>(),
+        ),
+        (
+            HomebrewPackageType::Cask,
+            "cask",
+            backup.casks.iter().map(|c| &c.name).collect(),
+        ),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        let index = match formula_index::load_index(&package_type).await {
+            Ok(index) => index,
+            Err(e) => {
+                println!("  {} Skipped checking {}s: {}", "!".yellow(), section, e);
+                continue;
+            }
+        };
+        let known = index
+            .iter()
+            .flat_map(|entry| std::iter::once(&entry.name).chain(&entry.aliases))
+            .collect::<HashSet<_>>();
+
+        for name in names.into_iter().filter(|name| !known.contains(name)) {
+            if third_party_taps > 0 {
+                // Formulae from third-party taps aren't in Homebrew's index
+                println!(
+                    "  {} {} {} isn't in Homebrew's index, it may come from one of the backup's taps",
+                    "!".yellow(),
+                    section,
+                    name
+                );
+            } else {
+                problems.push(format!("unknown {} {}", section, name));
+            }
+        }
+    }
+
+    for package in backup.node.iter().flat_map(|node| &node.packages) {
+        match node::get_package_info(&package.name).await {
+            Ok(Some(info)) if info.versions.contains_key(&package.version) => {}
+            Ok(Some(_)) => problems.push(format!(
+                "{}@{} is not a published version",
+                package.name, package.version
+            )),
+            Ok(None) => problems.push(format!("unknown Node.js package {}", package.name)),
+            Err(e) => println!(
+                "  {} Skipped checking {}: {}",
+                "!".yellow(),
+                package.name,
+                e
+            ),
+        }
+    }
+
+    problems
+}
+
 fn get_backup_path(custom_path: Option<&str>) -> Result<PathBuf> {
     if let Some(path) = custom_path {
         Ok(PathBuf::from(path))
@@ -225,6 +779,8 @@ fn get_latest_backup() -> Result<PathBuf> {
                 .map(|ext| ext == "json")
                 .unwrap_or(false)
         })
+        // Retry files only hold what a restore failed to install
+        .filter(|entry| !entry.file_name().to_string_lossy().ends_with(".retry.json"))
         .collect();
 
     backups.sort_by_key(|entry| entry.metadata().unwrap().modified().unwrap());
@@ -234,3 +790,120 @@ fn get_latest_backup() -> Result<PathBuf> {
         .map(|entry| entry.path())
         .ok_or_else(|| anyhow::anyhow!("No backup files found"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn backup(value: Value) -> BackupFile {
+        let mut value = value;
+        migrate(&mut value, 1).unwrap();
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn migrates_v1_backups() {
+        let backup = load_backup(&fixture("backup_v1.json")).unwrap();
+
+        assert_eq!(backup.schema_version, SCHEMA_VERSION);
+        assert!(backup.node.is_none());
+        assert_eq!(backup.formulas.len(), 2);
+        for formula in &backup.formulas {
+            // Scraped options are dropped and every formula counts as requested
+            assert!(formula.options.is_empty());
+            assert!(formula.installed_on_request);
+            assert!(!formula.installed_as_dependency);
+            assert!(formula.is_top_level());
+        }
+        assert_eq!(backup.casks[0].name, "firefox");
+        assert_eq!(backup.taps, ["homebrew/cask", "oven-sh/bun"]);
+    }
+
+    #[test]
+    fn migrates_v2_backups() {
+        let backup = load_backup(&fixture("backup_v2.json")).unwrap();
+
+        assert_eq!(backup.schema_version, SCHEMA_VERSION);
+        assert!(backup.formulas[0].options.is_empty());
+        assert!(backup.formulas[0].is_top_level());
+
+        let node = backup.node.expect("node packages survive the migration");
+        assert_eq!(node.package_manager, "npm");
+        assert_eq!(node.packages[0].name, "typescript");
+        assert_eq!(node.packages[0].version, "5.3.3");
+    }
+
+    #[test]
+    fn rejects_future_schema_versions() {
+        let value = serde_json::json!({ "schema_version": SCHEMA_VERSION + 1 });
+        assert!(schema_version(&value).is_err());
+        assert!(schema_version(&serde_json::json!({ "schema_version": 0 })).is_err());
+        assert!(schema_version(&serde_json::json!({ "schema_version": "2" })).is_err());
+        assert_eq!(schema_version(&serde_json::json!({})).unwrap(), 1);
+    }
+
+    #[test]
+    fn flags_schema_problems() {
+        let backup = backup(serde_json::json!({
+            "created_at": "yesterday",
+            "formulas": [
+                { "name": "jq", "version": "1.7.1", "options": [] },
+                { "name": "jq", "version": "1.7.1", "options": [] },
+                { "name": " ", "version": "1", "options": [] }
+            ],
+            "casks": [{ "name": "", "version": "1" }],
+            "taps": ["homebrew/core", "badtap", "user/"],
+            "node": {
+                "package_manager": "npx",
+                "packages": [
+                    { "name": "typescript", "version": "5.3.3" },
+                    { "name": "typescript", "version": "5.3.3" }
+                ]
+            }
+        }));
+        let problems = schema_problems(&backup);
+
+        let expected = [
+            "created_at: 'yesterday' is not an RFC 3339 timestamp",
+            "taps: 'badtap' is not a user/repo tap name",
+            "taps: 'user/' is not a user/repo tap name",
+            "formulas[1]: duplicate entry for jq",
+            "formulas[2]: empty name",
+            "casks[0]: empty name",
+            "node.packages[1]: duplicate entry for typescript",
+        ];
+        for problem in expected {
+            assert!(
+                problems.iter().any(|p| p == problem),
+                "missing {:?} in {:?}",
+                problem,
+                problems
+            );
+        }
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("node.package_manager:")));
+        assert_eq!(problems.len(), expected.len() + 1);
+    }
+
+    #[test]
+    fn retries_write_back_to_the_same_file() {
+        let backup = Path::new("/backups/bert_backup_20240101.json");
+        let retry = retry_path_for(backup);
+
+        assert_eq!(retry, Path::new("/backups/bert_backup_20240101.retry.json"));
+        assert_eq!(retry_path_for(&retry), retry);
+    }
+
+    #[test]
+    fn accepts_a_clean_backup() {
+        let backup = load_backup(&fixture("backup_v2.json")).unwrap();
+        assert!(schema_problems(&backup).is_empty());
+    }
+}
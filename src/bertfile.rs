@@ -0,0 +1,305 @@
+use crate::backend::{InstalledPackage, PackageBackend, Registry};
+use crate::history::{self, Action};
+use crate::homebrew::{self, brew};
+use crate::spec::PackageSpec;
+use crate::{dry_run, tap, version_tap};
+use anyhow::{Context, Result};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Taps that come with every Homebrew install and are never pruned
+const BUILTIN_TAPS: [&str; 2] = ["homebrew/core", "homebrew/cask"];
+
+/// Node globals that ship with the package manager itself and are never pruned
+const BUILTIN_NODE_PACKAGES: [&str; 2] = ["npm", "corepack"];
+
+/// A hand-written toolchain manifest, e.g.
+///
+/// ```toml
+/// taps = ["oven-sh/bun"]
+/// formulae = ["jq", "terraform@1.5.7"]
+/// casks = ["firefox"]
+/// node = ["typescript@5.3"]
+/// ```
+///
+/// A section that's left out is ignored by `--prune`, an empty one removes everything.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bertfile {
+    pub taps: Option<Vec<String>>,
+    pub formulae: Option<Vec<String>>,
+    pub casks: Option<Vec<String>>,
+    pub node: Option<Vec<String>>,
+}
+
+impl Bertfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read Bertfile at {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid Bertfile: {}", path.display()))
+    }
+
+    /// The package sections present in the Bertfile, with the backend each belongs to.
+    fn sections(&self) -> Vec<(&'static str, &Vec<String>)> {
+        [
+            ("formula", &self.formulae),
+            ("cask", &self.casks),
+            ("node", &self.node),
+        ]
+        .into_iter()
+        .filter_map(|(backend, specs)| Some((backend, specs.as_ref()?)))
+        .collect()
+    }
+}
+
+#[derive(Default)]
+struct SyncSummary {
+    installed: usize,
+    removed: usize,
+    up_to_date: usize,
+    failed: Vec<String>,
+}
+
+pub async fn sync(path: &str, prune: bool) -> Result<()> {
+    let path = Path::new(path);
+    let bertfile = Bertfile::load(path)?;
+    let registry = Registry::load()?;
+    let backends = registry.all().collect::<Vec<&dyn PackageBackend>>();
+
+    history::track(
+        Action::Sync,
+        &backends,
+        sync_bertfile(path, &bertfile, &registry, prune),
+    )
+    .await
+}
+
+async fn sync_bertfile(
+    path: &Path,
+    bertfile: &Bertfile,
+    registry: &Registry,
+    prune: bool,
+) -> Result<()> {
+    let mut summary = SyncSummary::default();
+
+    println!("Syncing packages from {} 🐕", path.display());
+
+    println!("\n{}:", "Taps".cyan());
+    let installed_taps = tap::list_taps()?;
+    let taps = bertfile.taps.as_deref().unwrap_or_default();
+    for tap in taps {
+        if installed_taps.contains(tap) {
+            summary.up_to_date += 1;
+            continue;
+        }
+
+        if homebrew::run_brew_step(tap, &["tap", tap])? {
+            summary.installed += 1;
+        } else {
+            summary.failed.push(format!("tap {}", tap));
+        }
+    }
+
+    if prune && bertfile.taps.is_some() {
+        for tap in installed_taps
+            .iter()
+            .filter(|tap| !taps.contains(tap) && !BUILTIN_TAPS.contains(&tap.as_str()))
+        {
+            if homebrew::run_brew_step(&format!("untap {}", tap), &["untap", tap])? {
+                summary.removed += 1;
+            } else {
+                summary.failed.push(format!("untap {}", tap));
+            }
+        }
+    }
+
+    for (backend_name, specs) in bertfile.sections() {
+        if specs.is_empty() && !prune {
+            continue;
+        }
+
+        let backend = registry.get(backend_name)?;
+        println!("\n{}:", backend.display_name().cyan());
+        let installed = backend.list().await?;
+
+        for spec in specs {
+            let parsed = PackageSpec::parse(spec);
+            if is_satisfied(&installed, &parsed) {
+                summary.up_to_date += 1;
+                continue;
+            }
+            let PackageSpec { name, version } = parsed;
+
+            match backend.install(&name, version.as_deref()).await {
+                Ok(()) => summary.installed += 1,
+                Err(e) => {
+                    println!("  {} {}: {}", "✗".red(), spec, e);
+                    summary.failed.push(spec.clone());
+                }
+            }
+        }
+
+        if prune {
+            let listed = specs
+                .iter()
+                .flat_map(|spec| installed_names(&PackageSpec::parse(spec)))
+                .collect::<Vec<_>>();
+
+            // Only prune formulae that were installed on purpose, not their dependencies
+            let candidates = if backend_name == "formula" {
+                list_leaves()?
+            } else {
+                installed
+                    .iter()
+                    .map(|package| package.name.clone())
+                    .collect()
+            };
+
+            let is_builtin =
+                |name: &str| backend_name == "node" && BUILTIN_NODE_PACKAGES.contains(&name);
+
+            for name in candidates
+                .iter()
+                .filter(|name| !listed.contains(name) && !is_builtin(name))
+            {
+                match backend.uninstall(name).await {
+                    Ok(()) => summary.removed += 1,
+                    Err(e) => {
+                        println!("  {} {}: {}", "✗".red(), name, e);
+                        summary.failed.push(format!("remove {}", name));
+                    }
+                }
+            }
+        }
+    }
+
+    if dry_run::is_enabled() {
+        println!("\n{}", "Dry run completed, nothing was changed.".yellow());
+    } else {
+        println!("\n{}", "Sync completed!".green());
+    }
+    println!("Summary:");
+    println!("  Installed: {}", summary.installed);
+    println!("  Up to date: {}", summary.up_to_date);
+    if prune {
+        println!("  Removed: {}", summary.removed);
+    }
+
+    if !summary.failed.is_empty() {
+        println!("  Failed: {}", summary.failed.join(", ").red());
+        anyhow::bail!("{} entries failed to sync", summary.failed.len());
+    }
+
+    Ok(())
+}
+
+/// A pinned version matches the installed one exactly or as a prefix (`20` matches `20.11.1`).
+/// Versioned formulae (`node@20`, or `terraform@1.5.7` from the versions tap) are installed
+/// under their own `name@version` name and match whatever version that formula is at.
+fn is_satisfied(installed: &[InstalledPackage], spec: &PackageSpec) -> bool {
+    // `brew list` shows formulae from other taps by their short name
+    let name = match spec.tap() {
+        Some(tap) => &spec.name[tap.len() + 1..],
+        None => spec.name.as_str(),
+    };
+    let version = spec.version.as_deref();
+    let versioned_name = version.map(|version| format!("{}@{}", name, version));
+    installed.iter().any(|package| {
+        Some(&package.name) == versioned_name.as_ref()
+            || package.name == name
+                && version.is_none_or(|version| {
+                    package.version == version
+                        || package.version.starts_with(&format!("{}.", version))
+                })
+    })
+}
+
+/// The names an entry can be installed under, which `--prune` has to keep: the plain name,
+/// and for versioned entries the `name@version` formula as `brew leaves` lists it.
+fn installed_names(spec: &PackageSpec) -> Vec<String> {
+    let mut names = vec![spec.name.clone()];
+    if let Some(version) = &spec.version {
+        let versioned_name = format!("{}@{}", spec.name, version);
+        names.push(format!("{}/{}", version_tap::VERSIONS_TAP, versioned_name));
+        names.push(versioned_name);
+    }
+    names
+}
+
+fn list_leaves() -> Result<Vec<String>> {
+    let output = Command::new(brew())
+        .args(["leaves", "--installed-on-request"])
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!("Failed to list top-level formulae");
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(name: &str, version: &str) -> InstalledPackage {
+        InstalledPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn omitted_sections_are_not_synced() {
+        let bertfile: Bertfile = toml::from_str("formulae = [\"jq\"]\nnode = []").unwrap();
+        let sections = bertfile
+            .sections()
+            .into_iter()
+            .map(|(backend, _)| backend)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sections, ["formula", "node"]);
+        assert!(bertfile.taps.is_none());
+    }
+
+    #[test]
+    fn versioned_formulae_match_their_own_name() {
+        let packages = [
+            installed("terraform@1.5.7", "1.5.7"),
+            installed("node@20", "20.11.1"),
+        ];
+
+        let satisfied = |spec| is_satisfied(&packages, &PackageSpec::parse(spec));
+
+        assert!(satisfied("terraform@1.5.7"));
+        assert!(satisfied("node@20"));
+        assert!(!satisfied("terraform"));
+        assert!(!satisfied("terraform@1.6.0"));
+    }
+
+    #[test]
+    fn tap_qualified_entries_match_the_short_name() {
+        let packages = [installed("bun", "1.1.8"), installed("@vue/cli", "5.0.8")];
+        let satisfied = |spec| is_satisfied(&packages, &PackageSpec::parse(spec));
+
+        assert!(satisfied("oven-sh/bun/bun"));
+        assert!(satisfied("oven-sh/bun/bun@1.1"));
+        assert!(!satisfied("oven-sh/bun/bun@1.2"));
+        assert!(satisfied("@vue/cli"));
+        assert!(!satisfied("other/tap/cli"));
+    }
+
+    #[test]
+    fn prune_keeps_versioned_formulae() {
+        let names = installed_names(&PackageSpec::parse("terraform@1.5.7"));
+
+        assert!(names.contains(&"terraform@1.5.7".to_string()));
+        assert!(names.contains(&"bert/versions/terraform@1.5.7".to_string()));
+    }
+}
@@ -1,10 +1,21 @@
+use crate::backend::Registry;
+use crate::config::{AutoInstall, Config};
+use crate::ephemeral;
+use crate::executable_index::{self, Provider};
 use crate::platform::Platform;
+use crate::prompt;
+use crate::spec::PackageSpec;
 use anyhow::Result;
 use colored::*;
-use std::process::{Command, Stdio};
+use dialoguer::{theme::ColorfulTheme, Select};
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use tokio::process::Command;
 use which::which;
 
-pub async fn handle_command(args: &[String]) -> Result<()> {
+/// Runs `args[0]`, installing it first if needed, and returns how it exited.
+pub async fn handle_command(args: &[String]) -> Result<ExitStatus> {
     let command = &args[0];
     let command_name = if Platform::current() == Platform::Windows && !command.ends_with(".exe") {
         format!("{}.exe", command)
@@ -15,32 +26,195 @@ pub async fn handle_command(args: &[String]) -> Result<()> {
     // Check if command exists
     if which(&command_name).is_err() {
         println!("{} not found. Attempting to install 🐕", command.yellow());
+        install_missing(command).await?;
+    }
 
-        // Try to install via homebrew
-        if let Err(e) = crate::package_manager::install_package(command, false, false).await {
-            println!("Failed to install {} with homebrew: {}", command.red(), e);
-            println!("Trying NPM instead 🐕");
+    run_command(Path::new(&command_name), &args[1..]).await
+}
 
-            if let Err(e) = crate::package_manager::install_package(command, false, true).await {
-                println!("Failed to install {} with NPM: {}", command.red(), e);
-                anyhow::bail!("Failed to install {}", command);
-            }
+/// Runs a package once from its cached prefix under `~/.bert/ephemeral`, without
+/// installing it globally.
+pub async fn run_ephemeral(
+    package: &str,
+    is_node: bool,
+    bin: Option<&str>,
+    args: &[String],
+) -> Result<ExitStatus> {
+    let spec = PackageSpec::parse(package);
+    let prepared = ephemeral::prepare(&spec, is_node, bin).await?;
+    run_command(&prepared.executable, args).await
+}
 
-            return Ok(());
-        }
+/// Runs `program` with inherited stdio and returns its exit status, so bert can exit
+/// exactly like the command it wraps.
+async fn run_command(program: &Path, args: &[String]) -> Result<ExitStatus> {
+    if crate::dry_run::is_enabled() {
+        let args = args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+        crate::dry_run::print_command(&program.to_string_lossy(), &args);
+        return Ok(ExitStatus::default());
     }
 
     // Execute command with remaining args
-    let status = Command::new(&command_name)
-        .args(&args[1..])
+    let mut child = Command::new(program)
+        .args(args)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .status()?;
+        .spawn()?;
+
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            // The child receives Ctrl-C as well, wait for it to decide how to exit
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    };
+
+    Ok(status)
+}
+
+/// Exits the way a wrapped command did. When it was killed by a signal, bert dies from
+/// the same signal so the parent sees a signal death rather than an exit code.
+pub fn exit_like(status: ExitStatus) -> ! {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            // SAFETY: restoring the default disposition and raising a signal only affect
+            // this process, which is about to exit anyway
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+            // Signals whose default action isn't to terminate, as shells report them
+            std::process::exit(128 + signal);
+        }
+    }
+
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+/// A package that would be installed for a missing command.
+struct Candidate {
+    package: String,
+    backend: &'static str,
+    version: String,
+}
+
+/// Installs the package providing `command`, subject to the `auto_install` policy.
+async fn install_missing(command: &str) -> Result<()> {
+    let config = Config::load()?;
+    if config.auto_install == AutoInstall::Never {
+        anyhow::bail!("Auto-install is disabled (auto_install is set to never)");
+    }
+
+    let candidate = find_candidate(command)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No package provides {}", command))?;
+
+    let registry = Registry::load()?;
+    let source = registry.get(candidate.backend)?.source();
+    println!(
+        "{} is provided by {} {} from {}",
+        command.cyan(),
+        candidate.package.green(),
+        candidate.version,
+        source
+    );
+
+    let allowed = config.auto_install == AutoInstall::Always
+        || config.trusted_packages.contains(&candidate.package)
+        || prompt::confirm(&format!(
+            "Install {} {} from {}?",
+            candidate.package, candidate.version, source
+        ))?;
+
+    if !allowed {
+        anyhow::bail!(
+            "Not installing {}{}",
+            candidate.package,
+            if std::io::stdin().is_terminal() {
+                ""
+            } else {
+                " (pass --yes to install without a prompt)"
+            }
+        );
+    }
+
+    crate::package_manager::install_package(&candidate.package, false, candidate.backend == "node")
+        .await
+}
+
+/// Known providers come from the executable index; otherwise the command name is tried
+/// as a formula and then as an npm package.
+async fn find_candidate(command: &str) -> Result<Option<Candidate>> {
+    let providers = executable_index::providers(command).await;
+    if let Some(provider) = choose_provider(command, &providers)? {
+        return Ok(Some(Candidate {
+            package: provider.package.clone(),
+            backend: provider.backend,
+            version: provider
+                .version
+                .clone()
+                .unwrap_or_else(|| "latest".to_string()),
+        }));
+    }
+
+    let registry = Registry::load()?;
+    for backend in [registry.get("formula")?, registry.get("node")?] {
+        if let Ok(Some(info)) = backend.info(command).await {
+            return Ok(Some(Candidate {
+                package: info.name,
+                backend: backend.name(),
+                version: info.version,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Picks between several packages that provide the same executable.
+fn choose_provider<'a>(command: &str, providers: &'a [Provider]) -> Result<Option<&'a Provider>> {
+    if providers.len() < 2 || !std::io::stdin().is_terminal() {
+        return Ok(providers.first());
+    }
+
+    let items = providers
+        .iter()
+        .map(|provider| {
+            format!(
+                "{} ({} {})",
+                provider.package,
+                provider.backend,
+                provider.version.as_deref().unwrap_or("latest")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Several packages provide {}", command))
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    Ok(providers.get(selection))
+}
+
+/// Called by the shell hook from `bert shell-init` when `command` isn't found.
+/// Returns whether the command was installed and can be re-run.
+pub async fn offer_install(command: &str) -> Result<bool> {
+    // Never prompt from scripts or pipelines, unless no prompt is needed
+    let no_prompt = prompt::assume_yes() || Config::load()?.auto_install == AutoInstall::Always;
+    if !std::io::stdin().is_terminal() && !no_prompt {
+        return Ok(false);
+    }
 
-    if !status.success() {
-        anyhow::bail!("Command failed with exit code: {}", status);
+    println!("{} is not installed.", command.yellow());
+    if let Err(e) = install_missing(command).await {
+        println!("{}", e.to_string().red());
+        return Ok(false);
     }
 
-    Ok(())
+    Ok(which(command).is_ok())
 }
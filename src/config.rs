@@ -11,6 +11,69 @@ pub struct Config {
     pub backup_dir: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom_settings: Option<serde_json::Value>,
+    /// How long cached API responses are used before being revalidated
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Base URL of the Homebrew formulae API (overridden by BERT_HOMEBREW_API_URL)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homebrew_api_url: Option<String>,
+    /// Base URL of the npm registry (overridden by BERT_NPM_REGISTRY_URL)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub npm_registry_url: Option<String>,
+    /// Base URL of the GitHub API used by self-update (overridden by BERT_GITHUB_API_URL)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub github_api_url: Option<String>,
+    /// Whether `bert <command>` installs missing commands without asking
+    #[serde(default)]
+    pub auto_install: AutoInstall,
+    /// Packages that are installed for missing commands without asking
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_packages: Vec<String>,
+    /// Third-party taps that may be tapped implicitly, e.g. for `oven-sh/bun/bun`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_taps: Vec<String>,
+    /// Days an unused `bert run` package is kept in ~/.bert/ephemeral
+    #[serde(default = "default_ephemeral_ttl_days")]
+    pub ephemeral_ttl_days: u64,
+    /// How many installs `bert restore` runs at once
+    #[serde(default = "default_restore_jobs")]
+    pub restore_jobs: usize,
+    /// Packages that `bert update` leaves alone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pins: Vec<Pin>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoInstall {
+    #[default]
+    Ask,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pin {
+    /// Backend the package belongs to ("formula", "cask" or "node")
+    pub backend: String,
+    pub name: String,
+    pub version: String,
+}
+
+const DEFAULT_HOMEBREW_API_URL: &str = "https://formulae.brew.sh/api";
+const DEFAULT_NPM_REGISTRY_URL: &str = "https://registry.npmjs.org";
+const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+fn default_cache_ttl_secs() -> u64 {
+    60 * 60
+}
+
+fn default_ephemeral_ttl_days() -> u64 {
+    30
+}
+
+fn default_restore_jobs() -> usize {
+    4
 }
 
 impl Default for Config {
@@ -19,6 +82,16 @@ impl Default for Config {
             node_package_manager: "npm".to_string(),
             backup_dir: None,
             custom_settings: None,
+            cache_ttl_secs: default_cache_ttl_secs(),
+            homebrew_api_url: None,
+            npm_registry_url: None,
+            github_api_url: None,
+            auto_install: AutoInstall::default(),
+            trusted_packages: Vec::new(),
+            trusted_taps: Vec::new(),
+            ephemeral_ttl_days: default_ephemeral_ttl_days(),
+            restore_jobs: default_restore_jobs(),
+            pins: Vec::new(),
         }
     }
 }
@@ -64,6 +137,71 @@ impl Config {
             _ => Ok(NodePackageManager::Npm), // Default to npm if invalid
         }
     }
+
+    pub fn pin(&mut self, backend: &str, name: &str, version: &str) -> Result<()> {
+        self.pins
+            .retain(|pin| !(pin.backend == backend && pin.name == name));
+        self.pins.push(Pin {
+            backend: backend.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+        });
+        self.save()
+    }
+
+    /// Removes a pin, returning whether the package was pinned.
+    pub fn unpin(&mut self, backend: &str, name: &str) -> Result<bool> {
+        let count = self.pins.len();
+        self.pins
+            .retain(|pin| !(pin.backend == backend && pin.name == name));
+        if self.pins.len() == count {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn pinned_version(&self, backend: &str, name: &str) -> Option<&str> {
+        self.pins
+            .iter()
+            .find(|pin| pin.backend == backend && pin.name == name)
+            .map(|pin| pin.version.as_str())
+    }
+
+    pub fn homebrew_api_url(&self) -> String {
+        resolve_url(
+            "BERT_HOMEBREW_API_URL",
+            &self.homebrew_api_url,
+            DEFAULT_HOMEBREW_API_URL,
+        )
+    }
+
+    pub fn npm_registry_url(&self) -> String {
+        resolve_url(
+            "BERT_NPM_REGISTRY_URL",
+            &self.npm_registry_url,
+            DEFAULT_NPM_REGISTRY_URL,
+        )
+    }
+
+    pub fn github_api_url(&self) -> String {
+        resolve_url(
+            "BERT_GITHUB_API_URL",
+            &self.github_api_url,
+            DEFAULT_GITHUB_API_URL,
+        )
+    }
+}
+
+/// Picks a base URL from the environment, then the config file, then the default.
+fn resolve_url(env_var: &str, configured: &Option<String>, default: &str) -> String {
+    let url = std::env::var(env_var)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .or_else(|| configured.clone())
+        .unwrap_or_else(|| default.to_string());
+
+    url.trim_end_matches('/').to_string()
 }
 
 fn get_config_path() -> Result<PathBuf> {
@@ -0,0 +1,29 @@
+use colored::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// When set, commands that change the system are printed instead of run.
+pub fn set_enabled(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// When set, printed commands go to stderr, so stdout only holds `--output json|yaml`.
+pub fn set_print_to_stderr(enabled: bool) {
+    TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+/// Prints the invocation that would have been run.
+pub fn print_command(program: &str, args: &[&str]) {
+    let line = format!("  {} {} {}", "[dry-run]".yellow(), program, args.join(" "));
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,430 @@
+use crate::config::Config;
+use crate::dry_run;
+use crate::homebrew::{self, brew, HomebrewPackageType};
+use crate::node::{self, NodePackageManager};
+use crate::spec::{self, PackageSpec};
+use crate::version::VersionReq;
+use anyhow::{Context, Result};
+use colored::*;
+use dirs::home_dir;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+/// Written once a package is fully installed, and touched on every run for garbage collection
+const MARKER_FILE: &str = ".bert-last-used";
+
+/// A package ready to run from its prefix. The prefix is locked against garbage
+/// collection until this is dropped.
+pub struct Prepared {
+    pub executable: PathBuf,
+    _lock: Option<File>,
+}
+
+/// Installs `spec` into its own cached prefix under `~/.bert/ephemeral` and returns the
+/// executable to run. `bin` picks an executable when a package has several.
+///
+/// Concurrent runs coordinate through a lock file next to the prefix: installing holds it
+/// exclusively, running holds it shared.
+pub async fn prepare(spec: &PackageSpec, is_node: bool, bin: Option<&str>) -> Result<Prepared> {
+    collect_garbage()?;
+
+    let (backend, install_name) = match is_node {
+        true => ("node", node_install_name(spec).await?),
+        false => ("formula", formula_install_name(spec).await?),
+    };
+    let prefix = get_entry_dir(backend, &install_name)?;
+    let bin_dir = match is_node {
+        true => prefix.join("bin"),
+        false => prefix.join("keg").join("bin"),
+    };
+    let package_manager = match is_node {
+        true => Some(Config::load()?.get_node_package_manager()?),
+        false => None,
+    };
+
+    let marker = prefix.join(MARKER_FILE);
+    if dry_run::is_enabled() && !marker.exists() {
+        print_install_plan(&install_name, package_manager.as_ref(), &prefix);
+        return Ok(Prepared {
+            executable: bin_dir.join(bin.unwrap_or(default_bin(&spec.name))),
+            _lock: None,
+        });
+    }
+
+    let mut lock = lock_entry(&prefix, false)?;
+    if !marker.exists() {
+        drop(lock);
+        let exclusive = lock_entry(&prefix, true)?;
+        // Another run may have installed it while this one waited for the lock
+        if !marker.exists() {
+            // Start from scratch if an earlier attempt was interrupted
+            if prefix.exists() {
+                fs::remove_dir_all(&prefix)?;
+            }
+            fs::create_dir_all(&prefix)?;
+
+            let result = match &package_manager {
+                Some(package_manager) => install_node(package_manager, &install_name, &prefix),
+                None => install_formula(&install_name, &prefix),
+            };
+            if let Err(e) = result {
+                fs::remove_dir_all(&prefix).ok();
+                return Err(e);
+            }
+        }
+        fs::write(&marker, "")?;
+        drop(exclusive);
+        lock = lock_entry(&prefix, false)?;
+    }
+    fs::write(&marker, "")?;
+
+    Ok(Prepared {
+        executable: find_executable(&bin_dir, &spec.name, bin)?,
+        _lock: Some(lock),
+    })
+}
+
+/// Homebrew can only pour the current bottle of a formula, or of a versioned formula.
+async fn formula_install_name(spec: &PackageSpec) -> Result<String> {
+    let formula = homebrew::search_formula(&spec.name, Some(HomebrewPackageType::Formula))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Package {} not found in Homebrew", spec.name))?;
+
+    match spec.version.as_deref() {
+        None | Some("latest") => Ok(formula.name),
+        Some(version) if version == formula.versions.stable => Ok(formula.name),
+        Some(version) if formula.has_version(version) => {
+            Ok(format!("{}@{}", formula.name, version))
+        }
+        Some(version) => anyhow::bail!(
+            "{} {} has no bottle to run from, only {} and its versioned formulae",
+            formula.name,
+            version,
+            formula.versions.stable
+        ),
+    }
+}
+
+/// npm resolves tags and ranges at install time, so they're resolved here first to keep
+/// one prefix per published version and pick up new releases.
+async fn node_install_name(spec: &PackageSpec) -> Result<String> {
+    // Git, tarball and path specs are resolved by the package manager itself
+    if spec.is_direct() {
+        return Ok(spec.to_string());
+    }
+
+    let info = node::get_package_info(&spec.name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Package {} not found on npm", spec.name))?;
+    let requested = spec.version.as_deref().unwrap_or("latest");
+    let version = if spec::is_dist_tag(requested) {
+        info.dist_tags
+            .as_ref()
+            .and_then(|tags| tags.get(requested))
+            .and_then(|version| version.as_str())
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("{} has no '{}' tag", spec.name, requested))?
+    } else if info.versions.contains_key(requested) {
+        requested.to_string()
+    } else {
+        let versions = info.versions.keys().cloned().collect::<Vec<_>>();
+        VersionReq::parse(requested)
+            .ok()
+            .and_then(|req| req.max_satisfying(&versions).map(String::from))
+            .ok_or_else(|| anyhow::anyhow!("No version of {} matches {}", spec.name, requested))?
+    };
+
+    Ok(format!("{}@{}", spec.name, version))
+}
+
+fn print_install_plan(
+    install_name: &str,
+    package_manager: Option<&NodePackageManager>,
+    prefix: &Path,
+) {
+    match package_manager {
+        Some(package_manager) => {
+            let command = node_install_command(package_manager, install_name, prefix);
+            let args = command
+                .get_args()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>();
+            let args = args.iter().map(|arg| arg.as_ref()).collect::<Vec<_>>();
+            dry_run::print_command(package_manager.command(), &args);
+        }
+        None => {
+            dry_run::print_command(brew(), &["fetch", "--force-bottle", install_name]);
+            println!(
+                "  {} extract the {} bottle into {}",
+                "[dry-run]".yellow(),
+                install_name,
+                prefix.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// The package manager installs "globally" into a private prefix, with executables in
+/// `<prefix>/bin`.
+fn install_node(package_manager: &NodePackageManager, package: &str, prefix: &Path) -> Result<()> {
+    println!("Installing {} into a temporary prefix 🐕", package.cyan());
+    let status = node_install_command(package_manager, package, prefix).status()?;
+
+    if !status.success() {
+        anyhow::bail!("Failed to install {}", package);
+    }
+    Ok(())
+}
+
+/// Each package manager is pointed at the prefix its own way. pnpm refuses to install
+/// into a bin directory that isn't on `PATH`, so it's added for the install.
+fn node_install_command(
+    package_manager: &NodePackageManager,
+    package: &str,
+    prefix: &Path,
+) -> Command {
+    let bin_dir = prefix.join("bin");
+    let mut command = Command::new(package_manager.command());
+    match package_manager {
+        NodePackageManager::Npm => {
+            command
+                .args(["install", "-g", "--no-fund", "--no-audit", "--prefix"])
+                .arg(prefix);
+        }
+        NodePackageManager::Yarn => {
+            command
+                .args(["global", "add", "--prefix"])
+                .arg(prefix)
+                .arg("--global-folder")
+                .arg(prefix.join("global"));
+        }
+        NodePackageManager::Pnpm => {
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            let path = std::env::join_paths(
+                std::iter::once(bin_dir.clone()).chain(std::env::split_paths(&path)),
+            )
+            .unwrap_or(path);
+            command
+                .args(["add", "-g", "--global-dir"])
+                .arg(prefix.join("global"))
+                .env("PNPM_HOME", &bin_dir)
+                .env("PATH", path);
+        }
+        NodePackageManager::Bun => {
+            command.args(["add", "-g"]).env("BUN_INSTALL", prefix);
+        }
+    }
+    command.arg(package);
+    command
+}
+
+/// Downloads the formula's bottle and unpacks its keg, without linking anything into
+/// the Homebrew prefix.
+fn install_formula(install_name: &str, prefix: &Path) -> Result<()> {
+    let output = Command::new(brew())
+        .args(["--cache", "--force-bottle", install_name])
+        .output()?;
+    let bottle = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || bottle.is_empty() {
+        anyhow::bail!("Could not locate the bottle for {}", install_name);
+    }
+    check_runnable(install_name, &bottle)?;
+
+    println!("Fetching the {} bottle 🐕", install_name.cyan());
+    let status = Command::new(brew())
+        .args(["fetch", "--force-bottle", install_name])
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("Failed to fetch a bottle for {}", install_name);
+    }
+
+    let status = Command::new("tar")
+        .args(["-xzf", &bottle, "-C"])
+        .arg(prefix)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("Failed to unpack {}", bottle);
+    }
+
+    // Bottles unpack to `<name>/<version>/`, expose that as `keg`
+    let unexpected_layout = || anyhow::anyhow!("Unexpected bottle layout in {}", bottle);
+    let formula_dir = fs::read_dir(prefix)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir())
+        .ok_or_else(unexpected_layout)?;
+    let keg = fs::read_dir(&formula_dir)?
+        .filter_map(|entry| entry.ok())
+        .next()
+        .ok_or_else(unexpected_layout)?;
+
+    fs::rename(keg.path(), prefix.join("keg"))?;
+    fs::remove_dir_all(formula_dir)?;
+    Ok(())
+}
+
+/// A bottle is unpacked as it is, without Homebrew's relocation of `@@HOMEBREW_PREFIX@@`
+/// placeholders and without dependencies. Only bottles that need neither can run that way.
+fn check_runnable(install_name: &str, bottle: &str) -> Result<()> {
+    let formula = homebrew::brew_info(&["--formula", install_name])?
+        .formulae
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Package {} not found in Homebrew", install_name))?;
+
+    if !formula.dependencies.is_empty() {
+        anyhow::bail!(
+            "{} depends on {}, which `bert run` can't provide. Install it with `bert install {}` instead",
+            install_name,
+            formula.dependencies.join(", "),
+            install_name
+        );
+    }
+
+    // The cache path ends in `<name>--<version>.<tag>.bottle.tar.gz`
+    let tag = bottle
+        .split_once(&format!("{}--{}.", formula.name, formula.pkg_version()))
+        .and_then(|(_, rest)| rest.split(".bottle").next());
+    let cellar = tag
+        .and_then(|tag| formula.bottle.get("stable")?.files.get(tag))
+        .and_then(|file| file.cellar.as_deref());
+    if cellar != Some(":any_skip_relocation") {
+        anyhow::bail!(
+            "The {} bottle has to be relocated by Homebrew, so it can't run from a temporary prefix. Install it with `bert install {}` instead",
+            install_name,
+            install_name
+        );
+    }
+    Ok(())
+}
+
+fn find_executable(bin_dir: &Path, package: &str, bin: Option<&str>) -> Result<PathBuf> {
+    let mut executables = fs::read_dir(bin_dir)
+        .with_context(|| format!("{} has no executables", package))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    executables.sort();
+
+    let wanted = bin.unwrap_or(default_bin(package));
+    let name = match executables.as_slice() {
+        _ if executables.iter().any(|exe| exe == wanted) => wanted.to_string(),
+        [only] if bin.is_none() => only.clone(),
+        _ => anyhow::bail!(
+            "{} provides {}, choose one with --bin",
+            package,
+            executables.join(", ")
+        ),
+    };
+
+    Ok(bin_dir.join(name))
+}
+
+/// The executable named after the package, without an npm scope or Homebrew tap.
+fn default_bin(package: &str) -> &str {
+    package.rsplit('/').next().unwrap_or(package)
+}
+
+/// Removes entries that haven't been run for longer than `ephemeral_ttl_days`.
+fn collect_garbage() -> Result<()> {
+    let max_age = Duration::from_secs(Config::load()?.ephemeral_ttl_days * 24 * 60 * 60);
+    let root = get_ephemeral_dir()?;
+
+    for backend_dir in fs::read_dir(&root)?.filter_map(|entry| entry.ok()) {
+        let Ok(entries) = fs::read_dir(backend_dir.path()) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            // Lock files are removed along with their prefix
+            if entry.file_name().to_string_lossy().ends_with(".lock") {
+                continue;
+            }
+            let last_used = fs::metadata(entry.path().join(MARKER_FILE))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let expired = last_used
+                .and_then(|time| SystemTime::now().duration_since(time).ok())
+                .is_some_and(|age| age > max_age);
+
+            if !expired || dry_run::is_enabled() {
+                continue;
+            }
+            // A prefix that is being installed or run holds its lock
+            let Ok(lock) = open_lock(&entry.path()) else {
+                continue;
+            };
+            if lock.try_lock().is_ok() && fs::remove_dir_all(entry.path()).is_ok() {
+                fs::remove_file(lock_path(&entry.path())).ok();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn get_entry_dir(backend: &str, name: &str) -> Result<PathBuf> {
+    // Scoped npm names contain a slash, keep every entry one directory deep
+    let key = name.replace('/', "+");
+    Ok(get_ephemeral_dir()?.join(backend).join(key))
+}
+
+/// Locks the entry of `prefix`, shared or exclusively. Garbage collection deletes a lock
+/// file while holding it, so a lock that was taken on a deleted file is taken again.
+fn lock_entry(prefix: &Path, exclusive: bool) -> Result<File> {
+    loop {
+        let lock = open_lock(prefix)?;
+        match exclusive {
+            true => lock.lock()?,
+            false => lock.lock_shared()?,
+        }
+        if is_current(&lock, &lock_path(prefix)) {
+            return Ok(lock);
+        }
+    }
+}
+
+fn open_lock(prefix: &Path) -> Result<File> {
+    Ok(File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path(prefix))?)
+}
+
+/// Lock files sit next to their prefix, so removing a prefix doesn't remove its lock.
+fn lock_path(prefix: &Path) -> PathBuf {
+    let mut path = prefix.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// Whether `lock` is still the file at `path`.
+#[cfg(unix)]
+fn is_current(lock: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (lock.metadata(), fs::metadata(path)) {
+        (Ok(held), Ok(current)) => held.dev() == current.dev() && held.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Windows can't delete a file that is open, so a lock file that exists is the one held.
+#[cfg(not(unix))]
+fn is_current(_lock: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+fn get_ephemeral_dir() -> Result<PathBuf> {
+    let dir = home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?
+        .join(".bert")
+        .join("ephemeral");
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+
+    Ok(dir)
+}
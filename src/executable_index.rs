@@ -0,0 +1,304 @@
+use crate::config::Config;
+use crate::http_cache;
+use crate::node::{self, types::NpmPackageInfo};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// How many npm search results are checked for a matching `bin` entry
+const NPM_CANDIDATES: usize = 5;
+
+/// A package that installs an executable with the requested name.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    /// Backend the package comes from ("formula" or "node")
+    pub backend: &'static str,
+    pub package: String,
+    pub version: Option<String>,
+}
+
+/// Executable → package index, kept in `~/.bert/cache/executables.json`.
+///
+/// Formulae come from Homebrew's executables list, refreshed after `cache_ttl_secs`.
+/// npm can't be queried by executable, so node entries are learned from the `bin` fields
+/// of the manifests bert looks at, and a command npm has no package for is remembered too.
+#[derive(Default, Serialize, Deserialize)]
+struct ExecutableIndex {
+    #[serde(default)]
+    formulae_updated_at: Option<String>,
+    #[serde(default)]
+    formulae: HashMap<String, Vec<IndexedPackage>>,
+    #[serde(default)]
+    node: HashMap<String, NodeEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct IndexedPackage {
+    package: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct NodeEntry {
+    checked_at: String,
+    packages: Vec<IndexedPackage>,
+}
+
+#[derive(Deserialize)]
+struct NpmSearchResponse {
+    objects: Vec<NpmSearchObject>,
+}
+
+#[derive(Deserialize)]
+struct NpmSearchObject {
+    package: NpmSearchPackage,
+}
+
+#[derive(Deserialize)]
+struct NpmSearchPackage {
+    name: String,
+}
+
+/// Every known package that provides `command`, Homebrew formulae first. A source that
+/// can't be reached just contributes nothing.
+pub async fn providers(command: &str) -> Vec<Provider> {
+    let mut index = ExecutableIndex::load();
+    let ttl = Config::load()
+        .map(|config| config.cache_ttl_secs)
+        .unwrap_or(0);
+    let mut changed = false;
+
+    if !is_fresh(index.formulae_updated_at.as_deref(), ttl) {
+        if let Ok(Some(formulae)) = fetch_formula_executables().await {
+            index.formulae = formulae;
+            index.formulae_updated_at = Some(Local::now().to_rfc3339());
+            changed = true;
+        }
+    }
+
+    let node_is_fresh = index
+        .node
+        .get(command)
+        .is_some_and(|entry| is_fresh(Some(&entry.checked_at), ttl));
+    if !node_is_fresh {
+        let previous = index.node.remove(command);
+        match npm_lookup(&mut index, command).await {
+            Ok(()) => changed = true,
+            // Keep what was known when npm can't be reached
+            Err(_) => {
+                if let Some(previous) = previous {
+                    index.node.insert(command.to_string(), previous);
+                }
+            }
+        }
+    }
+
+    if changed {
+        index.save().ok();
+    }
+
+    let formulae = index
+        .formulae
+        .get(command)
+        .into_iter()
+        .flatten()
+        .map(|p| Provider {
+            backend: "formula",
+            package: p.package.clone(),
+            version: p.version.clone(),
+        });
+    let node = index
+        .node
+        .get(command)
+        .into_iter()
+        .flat_map(|entry| &entry.packages)
+        .map(|p| Provider {
+            backend: "node",
+            package: p.package.clone(),
+            version: p.version.clone(),
+        });
+    formulae.chain(node).collect()
+}
+
+impl ExecutableIndex {
+    fn path() -> Result<PathBuf> {
+        Ok(http_cache::get_cache_dir()?.join("executables.json"))
+    }
+
+    /// A missing or unreadable index is rebuilt from scratch.
+    fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        http_cache::write_atomic(&Self::path()?, serde_json::to_string(self)?.as_bytes())
+    }
+
+    /// Indexes every executable in a package manifest's `bin` field.
+    fn learn(&mut self, info: &NpmPackageInfo) {
+        let Some(version) = info.latest_version() else {
+            return;
+        };
+        let bin = info
+            .versions
+            .get(&version)
+            .and_then(|manifest| manifest.get("bin"));
+        let executables = match bin {
+            Some(serde_json::Value::Object(bins)) => bins.keys().cloned().collect(),
+            // A single string bin is named after the package, without its scope
+            Some(serde_json::Value::String(_)) => info
+                .name
+                .rsplit('/')
+                .next()
+                .map(String::from)
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let now = Local::now().to_rfc3339();
+        for executable in executables {
+            let entry = self.node.entry(executable).or_default();
+            entry.checked_at = now.clone();
+            entry.packages.retain(|p| p.package != info.name);
+            entry.packages.push(IndexedPackage {
+                package: info.name.clone(),
+                version: Some(version.clone()),
+            });
+        }
+    }
+}
+
+fn is_fresh(timestamp: Option<&str>, ttl: u64) -> bool {
+    timestamp
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .and_then(|at| u64::try_from(Local::now().signed_duration_since(at).num_seconds()).ok())
+        .is_some_and(|age| age < ttl)
+}
+
+/// Parses Homebrew's executables list, which has one `formula(version):exe exe ...`
+/// line per formula.
+async fn fetch_formula_executables() -> Result<Option<HashMap<String, Vec<IndexedPackage>>>> {
+    let url = format!(
+        "{}/internal/executables.txt",
+        Config::load()?.homebrew_api_url()
+    );
+    let Some(body) = http_cache::get(&url).await? else {
+        return Ok(None);
+    };
+
+    let mut formulae: HashMap<String, Vec<IndexedPackage>> = HashMap::new();
+    for (formula, executables) in String::from_utf8_lossy(&body)
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+    {
+        let (name, version) = match formula.split_once('(') {
+            Some((name, version)) => (name, Some(version.trim_end_matches(')').to_string())),
+            None => (formula, None),
+        };
+        for executable in executables.split_whitespace() {
+            formulae
+                .entry(executable.to_string())
+                .or_default()
+                .push(IndexedPackage {
+                    package: name.to_string(),
+                    version: version.clone(),
+                });
+        }
+    }
+
+    Ok(Some(formulae))
+}
+
+/// Checks the package named like `command`, then the top search results, stopping at the
+/// first one that provides it. Every manifest fetched on the way is added to the index.
+async fn npm_lookup(index: &mut ExecutableIndex, command: &str) -> Result<()> {
+    let provides = |index: &ExecutableIndex| {
+        index
+            .node
+            .get(command)
+            .is_some_and(|entry| !entry.packages.is_empty())
+    };
+
+    // Start from a clean entry, so a package that stopped providing the command drops out
+    index.node.insert(
+        command.to_string(),
+        NodeEntry {
+            checked_at: Local::now().to_rfc3339(),
+            packages: Vec::new(),
+        },
+    );
+
+    if let Some(info) = node::get_package_info(command).await? {
+        index.learn(&info);
+        if provides(index) {
+            return Ok(());
+        }
+    }
+
+    let url = reqwest::Url::parse_with_params(
+        &format!("{}/-/v1/search", Config::load()?.npm_registry_url()),
+        &[("text", command), ("size", &NPM_CANDIDATES.to_string())],
+    )?;
+    let Some(body) = http_cache::get(url.as_str()).await? else {
+        return Ok(());
+    };
+    let response: NpmSearchResponse = serde_json::from_slice(&body)?;
+
+    for name in response
+        .objects
+        .into_iter()
+        .map(|object| object.package.name)
+        .filter(|name| name != command)
+    {
+        if let Some(info) = node::get_package_info(&name).await? {
+            index.learn(&info);
+            if provides(index) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(json: &str) -> NpmPackageInfo {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn packages(index: &ExecutableIndex, command: &str) -> Vec<String> {
+        index.node[command]
+            .packages
+            .iter()
+            .map(|p| p.package.clone())
+            .collect()
+    }
+
+    #[test]
+    fn learns_every_executable_of_a_manifest() {
+        let mut index = ExecutableIndex::default();
+        index.learn(&manifest(
+            r#"{"name":"typescript","dist-tags":{"latest":"5.3.3"},
+                "versions":{"5.3.3":{"bin":{"tsc":"bin/tsc","tsserver":"bin/tsserver"}}}}"#,
+        ));
+        index.learn(&manifest(
+            r#"{"name":"@scope/tsc","dist-tags":{"latest":"1.0.0"},
+                "versions":{"1.0.0":{"bin":"cli.js"}}}"#,
+        ));
+
+        assert_eq!(packages(&index, "tsc"), ["typescript", "@scope/tsc"]);
+        assert_eq!(packages(&index, "tsserver"), ["typescript"]);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::config::Config;
+use crate::homebrew::{Cask, Formula, HomebrewPackageType};
+use crate::http_cache;
+use anyhow::Result;
+
+/// Minimum Jaro-Winkler similarity for a name to count as a typo match
+const SIMILARITY_THRESHOLD: f64 = 0.8;
+
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub desc: Option<String>,
+    pub version: String,
+    pub revision: u32,
+}
+
+impl IndexEntry {
+    /// The version as `brew list --versions` reports it, including the bottle revision.
+    pub fn full_version(&self) -> String {
+        if self.revision > 0 {
+            format!("{}_{}", self.version, self.revision)
+        } else {
+            self.version.clone()
+        }
+    }
+}
+
+impl From<Formula> for IndexEntry {
+    fn from(formula: Formula) -> Self {
+        Self {
+            name: formula.name,
+            aliases: formula.aliases,
+            desc: formula.desc,
+            version: formula.versions.stable,
+            revision: formula.revision,
+        }
+    }
+}
+
+impl From<Cask> for IndexEntry {
+    fn from(cask: Cask) -> Self {
+        Self {
+            name: cask.token,
+            aliases: vec![],
+            desc: cask.desc,
+            version: cask.version,
+            revision: 0,
+        }
+    }
+}
+
+/// Loads the full formula or cask index through the metadata cache.
+pub async fn load_index(package_type: &HomebrewPackageType) -> Result<Vec<IndexEntry>> {
+    let api_url = Config::load()?.homebrew_api_url();
+    let url = match package_type {
+        HomebrewPackageType::Formula => format!("{}/formula.json", api_url),
+        HomebrewPackageType::Cask => format!("{}/cask.json", api_url),
+    };
+
+    let content = http_cache::get(&url)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Package index not found at {}", url))?;
+
+    let entries = match package_type {
+        HomebrewPackageType::Formula => serde_json::from_slice::<Vec<Formula>>(&content)?
+            .into_iter()
+            .map(IndexEntry::from)
+            .collect(),
+        HomebrewPackageType::Cask => serde_json::from_slice::<Vec<Cask>>(&content)?
+            .into_iter()
+            .map(IndexEntry::from)
+            .collect(),
+    };
+
+    Ok(entries)
+}
+
+/// Returns up to `limit` entries matching `query`, best match first.
+pub fn fuzzy_search(entries: Vec<IndexEntry>, query: &str, limit: usize) -> Vec<IndexEntry> {
+    let query = query.to_lowercase();
+
+    let mut matches = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let score = score_entry(&entry, &query);
+            if score > 0.0 {
+                Some((score, entry))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    matches.sort_by(|(a_score, a), (b_score, b)| {
+        b_score.total_cmp(a_score).then_with(|| a.name.cmp(&b.name))
+    });
+
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+fn score_entry(entry: &IndexEntry, query: &str) -> f64 {
+    let name_score = std::iter::once(&entry.name)
+        .chain(entry.aliases.iter())
+        .map(|name| score_name(&name.to_lowercase(), query))
+        .fold(0.0, f64::max);
+
+    let desc_score = match &entry.desc {
+        Some(desc) if desc.to_lowercase().contains(query) => 200.0,
+        _ => 0.0,
+    };
+
+    name_score.max(desc_score)
+}
+
+fn score_name(name: &str, query: &str) -> f64 {
+    // Shorter names win ties so `node` ranks above `node-build` for "node"
+    let length_penalty = name.len().abs_diff(query.len()) as f64;
+
+    if name == query {
+        1000.0
+    } else if name.starts_with(query) {
+        800.0 - length_penalty
+    } else if name.contains(query) {
+        600.0 - length_penalty
+    } else {
+        let similarity = strsim::jaro_winkler(name, query);
+        if similarity >= SIMILARITY_THRESHOLD {
+            500.0 * similarity
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, desc: &str) -> IndexEntry {
+        IndexEntry {
+            name: name.to_string(),
+            aliases: vec![],
+            desc: Some(desc.to_string()),
+            version: "1.0".to_string(),
+            revision: 0,
+        }
+    }
+
+    fn names(entries: Vec<IndexEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.name).collect()
+    }
+
+    fn index() -> Vec<IndexEntry> {
+        vec![
+            entry("python", "Interpreted language"),
+            entry("deno", "Runtime that is not Node"),
+            entry("libnode", "Embeddable JavaScript"),
+            entry("node-build", "Install Node.js versions"),
+            entry("nodf", "Typo neighbour"),
+            entry("node", "JavaScript runtime"),
+        ]
+    }
+
+    #[test]
+    fn ranks_exact_prefix_contains_typo_then_description() {
+        assert_eq!(
+            names(fuzzy_search(index(), "Node", 10)),
+            ["node", "node-build", "libnode", "nodf", "deno"]
+        );
+    }
+
+    #[test]
+    fn returns_at_most_limit_entries() {
+        assert_eq!(
+            names(fuzzy_search(index(), "node", 2)),
+            ["node", "node-build"]
+        );
+        assert!(fuzzy_search(index(), "node", 0).is_empty());
+    }
+
+    #[test]
+    fn aliases_match_like_names_and_ties_sort_by_name() {
+        let mut nodejs = entry("nodejs-lts", "LTS release");
+        nodejs.aliases.push("node".to_string());
+        let entries = vec![entry("node-b", ""), entry("node-a", ""), nodejs];
+
+        assert_eq!(
+            names(fuzzy_search(entries, "node", 10)),
+            ["nodejs-lts", "node-a", "node-b"]
+        );
+    }
+}
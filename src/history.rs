@@ -0,0 +1,329 @@
+use crate::backend::{PackageBackend, Registry};
+use crate::{dry_run, version};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use colored::*;
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Install,
+    Uninstall,
+    Update,
+    Restore,
+    Sync,
+    Undo,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Action::Install => "install",
+            Action::Uninstall => "uninstall",
+            Action::Update => "update",
+            Action::Restore => "restore",
+            Action::Sync => "sync",
+            Action::Undo => "undo",
+        };
+        f.pad(name)
+    }
+}
+
+/// One package whose installed version changed. `None` means not installed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub backend: String,
+    pub package: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: u64,
+    pub timestamp: String,
+    pub action: Action,
+    pub changes: Vec<Change>,
+    /// For undo transactions, the id of the transaction that was reversed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<u64>,
+}
+
+type Snapshot = HashMap<String, String>;
+
+async fn snapshot(backend: &dyn PackageBackend) -> Result<Snapshot> {
+    Ok(backend
+        .list()
+        .await?
+        .into_iter()
+        .map(|package| (package.name, package.version))
+        .collect())
+}
+
+fn diff(backend: &str, before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let mut names = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| Change {
+            backend: backend.to_string(),
+            package: name.clone(),
+            before: before.get(name).cloned(),
+            after: after.get(name).cloned(),
+        })
+        .collect()
+}
+
+/// Runs `operation` and records what it changed in `backends` as one transaction,
+/// even if the operation fails part way through.
+pub async fn track<F>(action: Action, backends: &[&dyn PackageBackend], operation: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    track_reverting(action, None, backends, operation).await
+}
+
+async fn track_reverting<F>(
+    action: Action,
+    reverts: Option<u64>,
+    backends: &[&dyn PackageBackend],
+    operation: F,
+) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    if dry_run::is_enabled() {
+        return operation.await;
+    }
+
+    let mut before = Vec::with_capacity(backends.len());
+    for backend in backends {
+        before.push(snapshot(*backend).await);
+    }
+
+    let result = operation.await;
+
+    // A backend that couldn't be listed would look like everything was added or removed
+    let mut changes = Vec::new();
+    for (backend, before) in backends.iter().zip(before) {
+        match (before, snapshot(*backend).await) {
+            (Ok(before), Ok(after)) => changes.extend(diff(backend.name(), &before, &after)),
+            (Err(e), _) | (_, Err(e)) => println!(
+                "{}",
+                format!("{}, so this change isn't in the history", e).yellow()
+            ),
+        }
+    }
+
+    if !changes.is_empty() {
+        append(action, reverts, changes)?;
+    }
+
+    result
+}
+
+fn append(action: Action, reverts: Option<u64>, changes: Vec<Change>) -> Result<()> {
+    let id = load()?.last().map_or(1, |transaction| transaction.id + 1);
+    let transaction = Transaction {
+        id,
+        timestamp: Local::now().to_rfc3339(),
+        action,
+        changes,
+        reverts,
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_history_path()?)?;
+    writeln!(file, "{}", serde_json::to_string(&transaction)?)?;
+    Ok(())
+}
+
+pub fn load() -> Result<Vec<Transaction>> {
+    let path = get_history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    // A damaged line shouldn't make the rest of the history unusable
+    Ok(fs::read_to_string(&path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match serde_json::from_str(line) {
+            Ok(transaction) => Some(transaction),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Skipping unreadable line {} of {}: {}",
+                        index + 1,
+                        path.display(),
+                        e
+                    )
+                    .yellow()
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+pub fn show_history(limit: usize) -> Result<()> {
+    let transactions = load()?;
+    if transactions.is_empty() {
+        println!("No history yet 🐕");
+        return Ok(());
+    }
+
+    let skip = transactions.len().saturating_sub(limit);
+    for transaction in transactions.iter().skip(skip) {
+        let timestamp = DateTime::parse_from_rfc3339(&transaction.timestamp)
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|_| transaction.timestamp.clone());
+
+        let mut heading = format!(
+            "{:>4}  {}  {}",
+            transaction.id, timestamp, transaction.action
+        );
+        if let Some(reverted) = transaction.reverts {
+            heading.push_str(&format!(" (reverts #{})", reverted));
+        }
+        println!("{}", heading.cyan());
+
+        for change in &transaction.changes {
+            println!(
+                "        {:<8} {:<30} {} → {}",
+                change.backend,
+                change.package,
+                change.before.as_deref().unwrap_or("none"),
+                change.after.as_deref().unwrap_or("none")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Reverses transaction `id`, or the most recent one that hasn't been undone yet.
+pub async fn undo(id: Option<u64>) -> Result<()> {
+    let transactions = load()?;
+    let is_undone = |id: u64| transactions.iter().any(|t| t.reverts == Some(id));
+
+    let transaction = match id {
+        Some(id) => transactions
+            .iter()
+            .find(|t| t.id == id)
+            .ok_or_else(|| anyhow::anyhow!("No transaction with id {}", id))?,
+        None => transactions
+            .iter()
+            .rev()
+            .find(|t| t.action != Action::Undo && !is_undone(t.id))
+            .ok_or_else(|| anyhow::anyhow!("Nothing to undo"))?,
+    };
+
+    if is_undone(transaction.id) {
+        anyhow::bail!("Transaction {} has already been undone", transaction.id);
+    }
+
+    println!(
+        "Undoing transaction #{} ({}) 🐕",
+        transaction.id, transaction.action
+    );
+
+    let registry = Registry::load()?;
+    let mut backends: Vec<&dyn PackageBackend> = Vec::new();
+    for change in &transaction.changes {
+        let backend = registry.get(&change.backend)?;
+        if !backends.iter().any(|b| b.name() == backend.name()) {
+            backends.push(backend);
+        }
+    }
+
+    track_reverting(Action::Undo, Some(transaction.id), &backends, async {
+        let mut failures = 0;
+        let mut report = |package: &str, result: Result<()>| {
+            if let Err(e) = result {
+                println!("  {} {}: {}", "✗".red(), package, e);
+                failures += 1;
+            }
+        };
+
+        // Remove what the transaction added first, dependents before their dependencies
+        for backend in &backends {
+            let added = transaction
+                .changes
+                .iter()
+                .filter(|change| change.backend == backend.name())
+                .filter(|change| change.before.is_none() && change.after.is_some())
+                .map(|change| change.package.clone())
+                .collect::<Vec<_>>();
+            for package in uninstall_order(*backend, &added).await {
+                report(&package, backend.uninstall(&package).await);
+            }
+        }
+
+        // Then put back the versions that were there before
+        for change in &transaction.changes {
+            if let Some(before) = &change.before {
+                let backend = registry.get(&change.backend)?;
+                let before = version::without_revision(before);
+                let result = match change.after {
+                    Some(_) => backend.revert(&change.package, before).await,
+                    None => backend.install(&change.package, Some(before)).await,
+                };
+                report(&change.package, result);
+            }
+        }
+
+        if failures > 0 {
+            anyhow::bail!("{} changes could not be reverted", failures);
+        }
+        Ok(())
+    })
+    .await?;
+
+    println!("{}", "Undo completed!".green());
+    Ok(())
+}
+
+/// `packages` in the order the backend can uninstall them. Anything the backend
+/// couldn't place keeps its original position at the end.
+async fn uninstall_order(backend: &dyn PackageBackend, packages: &[String]) -> Vec<String> {
+    let mut ordered = backend
+        .uninstall_order(packages)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|package| packages.contains(package))
+        .collect::<Vec<_>>();
+    for package in packages {
+        if !ordered.contains(package) {
+            ordered.push(package.clone());
+        }
+    }
+    ordered
+}
+
+fn get_history_path() -> Result<PathBuf> {
+    let bert_dir = home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?
+        .join(".bert");
+
+    if !bert_dir.exists() {
+        fs::create_dir_all(&bert_dir)?;
+    }
+
+    Ok(bert_dir.join("history.jsonl"))
+}
@@ -1,12 +1,23 @@
+use crate::backend::{InstalledPackage, OutdatedPackage, PackageBackend, PackageInfo};
+use crate::config::Config;
+use crate::dry_run;
+use crate::formula_index;
+use crate::http_cache;
+use crate::output;
 use crate::platform::Platform;
+use crate::prompt;
+use crate::spec::{self, PackageSpec};
+use crate::tap;
+use crate::version;
+use crate::version_tap;
 use anyhow::Result;
+use async_trait::async_trait;
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Confirm};
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::process::Command;
-use std::thread;
 use std::time::Duration;
 
 #[cfg(target_os = "windows")]
@@ -25,6 +36,7 @@ pub enum HomebrewPackageType {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Formula {
     pub name: String,
+    /// Tap-qualified name for formulae outside homebrew/core, e.g. `oven-sh/bun/bun`
     pub full_name: String,
     pub desc: Option<String>,
     pub homepage: Option<String>,
@@ -35,6 +47,8 @@ pub struct Formula {
     pub aliases: Vec<String>,
     pub tap: Option<String>,
     pub license: Option<String>,
+    #[serde(default)]
+    pub revision: u32,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -56,14 +70,149 @@ pub struct Versions {
     // pub bottle: bool,
 }
 
+/// `brew info --json=v2` output, which unlike the API also describes what's installed
+#[derive(Debug, Deserialize)]
+pub struct BrewInfo {
+    #[serde(default)]
+    pub formulae: Vec<FormulaInfo>,
+    #[serde(default)]
+    pub casks: Vec<CaskInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FormulaInfo {
+    pub name: String,
+    pub versions: Versions,
+    #[serde(default)]
+    pub revision: u32,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub installed: Vec<InstalledKeg>,
+    /// Bottles by spec, only `stable` matters to bert
+    #[serde(default)]
+    pub bottle: HashMap<String, BottleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InstalledKeg {
+    pub version: String,
+    /// Options the keg was built with, e.g. `--HEAD`
+    #[serde(default)]
+    pub used_options: Vec<String>,
+    #[serde(default)]
+    pub installed_as_dependency: bool,
+    #[serde(default)]
+    pub installed_on_request: bool,
+    #[serde(default)]
+    pub poured_from_bottle: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BottleSpec {
+    #[serde(default)]
+    pub rebuild: u32,
+    /// Bottle files by platform tag, e.g. `arm64_sonoma` or `all`
+    #[serde(default)]
+    pub files: HashMap<String, BottleFile>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BottleFile {
+    pub url: String,
+    pub sha256: String,
+    /// `:any_skip_relocation` for bottles that can be unpacked anywhere as they are
+    pub cellar: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CaskInfo {
+    pub token: String,
+    pub version: String,
+    /// Installed version, if any
+    pub installed: Option<String>,
+    /// Download checksum, or `no_check` for casks that don't pin one
+    pub sha256: Option<String>,
+}
+
+impl FormulaInfo {
+    /// The version as `brew list --versions` reports it, including the revision.
+    pub fn pkg_version(&self) -> String {
+        if self.revision > 0 {
+            format!("{}_{}", self.versions.stable, self.revision)
+        } else {
+            self.versions.stable.clone()
+        }
+    }
+}
+
+/// Runs `brew info --json=v2 <args>`, e.g. with `--installed` or a list of names.
+pub fn brew_info(args: &[&str]) -> Result<BrewInfo> {
+    let output = Command::new(brew())
+        .args(["info", "--json=v2"])
+        .args(args)
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "brew info failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Orders formulae so that each comes after the installed formulae it depends on.
+pub fn in_dependency_order(formulae: &[FormulaInfo]) -> Vec<&FormulaInfo> {
+    fn visit<'a>(
+        formula: &'a FormulaInfo,
+        by_name: &HashMap<&str, &'a FormulaInfo>,
+        visited: &mut HashSet<&'a str>,
+        ordered: &mut Vec<&'a FormulaInfo>,
+    ) {
+        if !visited.insert(&formula.name) {
+            return;
+        }
+        for dependency in &formula.dependencies {
+            if let Some(dependency) = by_name.get(dependency.as_str()) {
+                visit(dependency, by_name, visited, ordered);
+            }
+        }
+        ordered.push(formula);
+    }
+
+    let by_name = formulae
+        .iter()
+        .map(|formula| (formula.name.as_str(), formula))
+        .collect::<HashMap<_, _>>();
+    let mut visited = HashSet::new();
+    let mut ordered = Vec::new();
+    for formula in formulae {
+        visit(formula, &by_name, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
 impl Formula {
+    /// Whether Homebrew itself ships `version`, as the stable release or a versioned formula.
+    pub fn has_version(&self, version: &str) -> bool {
+        self.versions.stable == version
+            || self
+                .versioned_formulae
+                .contains(&format!("{}@{}", self.name, version))
+    }
+
+    /// The name to pass to `brew install`, qualified with the formula's tap.
     pub fn get_install_name(&self, version: Option<&str>) -> String {
         if let Some(v) = version {
             let versioned_name = format!("{}@{}", self.name, v);
-            if !self.versioned_formulae.is_empty()
+            if v == self.versions.stable {
+                self.full_name.clone()
+            } else if !self.versioned_formulae.is_empty()
                 && self.versioned_formulae.contains(&versioned_name)
             {
-                versioned_name
+                self.qualify(&versioned_name)
             } else {
                 println!("{}", format!("Warning: Version {} not found.", v).yellow());
 
@@ -88,10 +237,18 @@ impl Formula {
                 }
 
                 println!("{}", "Installing latest version instead 🐕".yellow());
-                self.name.clone()
+                self.full_name.clone()
             }
         } else {
-            self.name.clone()
+            self.full_name.clone()
+        }
+    }
+
+    /// Prefixes `name` with this formula's tap, if it's in one other than homebrew/core.
+    fn qualify(&self, name: &str) -> String {
+        match self.full_name.rsplit_once('/') {
+            Some((tap, _)) => format!("{}/{}", tap, name),
+            None => name.to_string(),
         }
     }
 }
@@ -142,6 +299,15 @@ pub fn display_package_info(formula: &Formula, is_cask: bool) {
     }
 }
 
+/// The `brew` executable to run.
+pub(crate) fn brew() -> &'static str {
+    if cfg!(windows) {
+        "brew.exe"
+    } else {
+        "brew"
+    }
+}
+
 pub async fn is_homebrew_installed() -> bool {
     match Platform::current() {
         Platform::Windows => which::which("brew.exe").is_ok(),
@@ -160,11 +326,7 @@ pub async fn is_homebrew_installed() -> bool {
 pub async fn install_homebrew() -> Result<()> {
     println!("{}", "Homebrew is required but not installed.".yellow());
 
-    if !Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt("Would you like to install Homebrew?")
-        .default(true)
-        .interact()?
-    {
+    if !prompt::confirm("Would you like to install Homebrew?")? {
         anyhow::bail!("Homebrew is required to continue.");
     }
 
@@ -190,7 +352,7 @@ pub async fn install_homebrew() -> Result<()> {
             let homebrew_path = home.join(".homebrew/bin");
 
             Command::new("powershell")
-                .args(&[
+                .args([
                     "-Command",
                     &format!("$env:Path += ';{}'", homebrew_path.display()),
                     "&",
@@ -231,7 +393,7 @@ pub async fn install_homebrew() -> Result<()> {
                     .expect("Failed to open shell configuration file");
 
                 config_file
-                    .write(homebrew_env.as_bytes())
+                    .write_all(homebrew_env.as_bytes())
                     .expect("Failed to write to shell configuration file");
             }
         }
@@ -251,44 +413,34 @@ pub async fn install_formula_version(
     is_cask: bool,
 ) -> Result<()> {
     if !is_homebrew_installed().await {
-        install_homebrew().await?;
+        if dry_run::is_enabled() {
+            println!(
+                "{}",
+                "Homebrew is not installed and would be installed first".yellow()
+            );
+        } else {
+            install_homebrew().await?;
+        }
     }
 
-    // For custom taps, we can install directly
-    if name.matches('/').count() == 2 {
-        println!("Installing {} via Homebrew 🐕", name.cyan());
-
-        let progress_bar = ProgressBar::new(100);
-        let mut child = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["install", name])
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
-
-        // Create a simple spinner style
-        progress_bar.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}"));
-        progress_bar.set_message(&format!("Installing {}", name));
-
-        while child.try_wait()?.is_none() {
-            progress_bar.tick();
-            thread::sleep(Duration::from_millis(100));
-        }
-
-        // Just wait for the process to complete
-        let status = child.wait()?;
-
-        if status.success() {
-            progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
-            progress_bar.finish_with_message(&format!(
-                "{} Successfully installed {}",
-                "✔".green(),
-                name
-            ));
-            return Ok(());
-        } else {
-            progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
-            progress_bar.finish_with_message(&format!("{} Failed to install {}", "✘".red(), name));
-            anyhow::bail!("Failed to install {}", name);
+    // Homebrew has no dist-tags, `latest` is just the stable release
+    let version = match version {
+        Some("latest") => None,
+        Some(tag) if spec::is_dist_tag(tag) => {
+            anyhow::bail!("Homebrew packages don't have a '{}' tag", tag)
         }
+        version => version,
+    };
+
+    // For custom taps, we can install directly
+    let spec = PackageSpec {
+        name: name.to_string(),
+        version: version.map(String::from),
+    };
+    if spec.tap().is_some() {
+        let install_name = spec.to_string();
+        println!("Installing {} via Homebrew 🐕", install_name.cyan());
+        return run_brew_install(&["install", &install_name], &install_name).await;
     }
 
     // Regular formula installation
@@ -302,6 +454,11 @@ pub async fn install_formula_version(
     )
     .await?
     {
+        // Versions Homebrew no longer ships are installed from homebrew-core history
+        if let Some(version) = version.filter(|v| !is_cask && !formula.has_version(v)) {
+            return version_tap::install_version(&formula.name, version).await;
+        }
+
         let install_name = formula.get_install_name(version);
 
         println!(
@@ -317,39 +474,70 @@ pub async fn install_formula_version(
         };
         args.push(&install_name);
 
-        let progress_bar = ProgressBar::new(100);
-        let mut child = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["install", name])
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
+        run_brew_install(&args, &install_name).await
+    } else {
+        anyhow::bail!("Package {} not found", name);
+    }
+}
 
-        // Create a simple spinner style
-        progress_bar.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}"));
-        progress_bar.set_message(&format!("Installing {}", name));
+/// Runs one `brew` step of a batch job such as a restore, printing `label` followed by
+/// ✓ or ✗. In dry-run mode only the command is printed.
+pub fn run_brew_step(label: &str, args: &[&str]) -> Result<bool> {
+    if dry_run::is_enabled() {
+        dry_run::print_command(brew(), args);
+        return Ok(true);
+    }
 
-        while child.try_wait()?.is_none() {
-            progress_bar.tick();
-            thread::sleep(Duration::from_millis(100));
-        }
+    print!("  {:<40}", label);
+    let status = Command::new(brew()).args(args).status()?;
 
-        // Just wait for the process to complete
-        let status = child.wait()?;
+    if status.success() {
+        println!("{}", "✓".green());
+    } else {
+        println!("{}", "✗".red());
+    }
 
-        if status.success() {
-            progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
-            progress_bar.finish_with_message(&format!(
-                "{} Successfully installed {}",
-                "✔".green(),
-                name
-            ));
-            return Ok(());
-        } else {
-            progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
-            progress_bar.finish_with_message(&format!("{} Failed to install {}", "✘".red(), name));
-            anyhow::bail!("Failed to install {}", name);
+    Ok(status.success())
+}
+
+/// Runs `brew <args>` behind a spinner, or only prints the command in dry-run mode.
+pub async fn run_brew_install(args: &[&str], name: &str) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::print_command(brew(), args);
+        return Ok(());
+    }
+
+    let progress_bar = ProgressBar::new(100);
+    let mut child = tokio::process::Command::new(brew())
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+
+    // Create a simple spinner style
+    progress_bar.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}"));
+    progress_bar.set_message(&format!("Installing {}", name));
+
+    // Tick while waiting without holding up the runtime thread
+    let mut ticks = tokio::time::interval(Duration::from_millis(100));
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            _ = ticks.tick() => progress_bar.tick(),
         }
+    };
+
+    if status.success() {
+        progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
+        progress_bar.finish_with_message(&format!(
+            "{} Successfully installed {}",
+            "✔".green(),
+            name
+        ));
+        Ok(())
     } else {
-        anyhow::bail!("Package {} not found", name);
+        progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
+        progress_bar.finish_with_message(&format!("{} Failed to install {}", "✘".red(), name));
+        anyhow::bail!("Failed to install {}", name);
     }
 }
 
@@ -365,16 +553,10 @@ pub async fn search_formula(
             let tap = format!("{}/{}", parts[0], parts[1]);
 
             // First ensure the tap is added
-            let tap_status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-                .args(["tap", &tap])
-                .status()?;
-
-            if !tap_status.success() {
-                anyhow::bail!("Failed to add tap {}", tap);
-            }
+            tap::ensure_tap(&tap)?;
 
             // Try to get formula info
-            let output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
+            let output = Command::new(brew())
                 .args(["info", "--json=v2", name])
                 .output()?;
 
@@ -392,46 +574,39 @@ pub async fn search_formula(
         }
         1 => {
             // Regular formula from main homebrew/core tap
-            let client = reqwest::Client::new();
+            let api_url = Config::load()?.homebrew_api_url();
+            let (endpoint, is_cask) = match package_type {
+                Some(HomebrewPackageType::Cask) => ("cask", true),
+                _ => ("formula", false),
+            };
 
-            let (api_url, is_cask) = match package_type {
-                Some(HomebrewPackageType::Cask) => ("https://formulae.brew.sh/api/cask", true),
-                _ => ("https://formulae.brew.sh/api/formula", false),
+            let url = format!("{}/{}/{}.json", api_url, endpoint, name);
+            let Some(body) = http_cache::get(&url).await? else {
+                return Ok(None);
             };
 
-            let url = format!("{}/{}.json", api_url, name);
-            let response = client.get(&url).send().await;
-
-            match response {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        if is_cask {
-                            let cask: Cask = resp.json().await?;
-                            // Convert Cask to Formula format for consistency
-                            Ok(Some(Formula {
-                                name: cask.token.clone(),
-                                full_name: cask.token,
-                                desc: cask.desc,
-                                homepage: cask.homepage,
-                                versions: Versions {
-                                    stable: cask.version,
-                                    // head: "".to_string(),
-                                    // bottle: false,
-                                },
-                                versioned_formulae: vec![],
-                                aliases: vec![],
-                                tap: cask.tap,
-                                license: None,
-                            }))
-                        } else {
-                            let formula: Formula = resp.json().await?;
-                            Ok(Some(formula))
-                        }
-                    } else {
-                        Ok(None)
-                    }
-                }
-                Err(_) => Ok(None),
+            if is_cask {
+                let cask: Cask = serde_json::from_slice(&body)?;
+                // Convert Cask to Formula format for consistency
+                Ok(Some(Formula {
+                    full_name: cask.token.clone(),
+                    name: cask.token,
+                    desc: cask.desc,
+                    homepage: cask.homepage,
+                    versions: Versions {
+                        stable: cask.version,
+                        // head: "".to_string(),
+                        // bottle: false,
+                    },
+                    versioned_formulae: vec![],
+                    aliases: vec![],
+                    tap: cask.tap,
+                    license: None,
+                    revision: 0,
+                }))
+            } else {
+                let formula: Formula = serde_json::from_slice(&body)?;
+                Ok(Some(formula))
             }
         }
         _ => {
@@ -444,19 +619,13 @@ pub async fn search_formula(
     }
 }
 
-pub async fn install_formula(name: &str, is_cask: bool) -> Result<()> {
-    install_formula_version(name, None, is_cask).await?;
-
-    Ok(())
-}
-
 pub async fn uninstall_formula(name: &str, is_cask: bool) -> Result<()> {
     if !is_homebrew_installed().await {
         anyhow::bail!("Homebrew is not installed");
     }
 
     // First check if the package is installed
-    let installed = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
+    let installed = Command::new(brew())
         .args([
             "list",
             "--versions",
@@ -476,14 +645,22 @@ pub async fn uninstall_formula(name: &str, is_cask: bool) -> Result<()> {
 
     println!("Uninstalling {} 🐕", name.cyan());
 
+    if dry_run::is_enabled() {
+        if is_cask {
+            dry_run::print_command(brew(), &["uninstall", "--cask", name]);
+        } else {
+            dry_run::print_command(brew(), &["uninstall", name]);
+        }
+        dry_run::print_command(brew(), &["cleanup", name]);
+        return Ok(());
+    }
+
     let status = if is_cask {
-        Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
+        Command::new(brew())
             .args(["uninstall", "--cask", name])
             .status()?
     } else {
-        Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["uninstall", name])
-            .status()?
+        Command::new(brew()).args(["uninstall", name]).status()?
     };
 
     if !status.success() {
@@ -491,41 +668,313 @@ pub async fn uninstall_formula(name: &str, is_cask: bool) -> Result<()> {
     }
 
     // Run cleanup
-    Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-        .args(["cleanup", name])
-        .status()?;
+    Command::new(brew()).args(["cleanup", name]).status()?;
 
     println!("{} {} successfully", "Uninstalled".green(), name);
     Ok(())
 }
 
-pub fn list_packages() -> Result<()> {
-    let formula_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-        .args(["list", "--versions", "--formula"])
-        .output()?;
+pub struct Homebrew {
+    package_type: HomebrewPackageType,
+}
+
+impl Homebrew {
+    pub fn new(package_type: HomebrewPackageType) -> Self {
+        Self { package_type }
+    }
+
+    fn is_cask(&self) -> bool {
+        matches!(self.package_type, HomebrewPackageType::Cask)
+    }
+}
 
-    if formula_output.status.success() {
-        let packages = String::from_utf8_lossy(&formula_output.stdout);
-        println!("{}", "Formulae:".cyan());
-        for package in packages.lines() {
-            println!("  {}", package);
+#[async_trait]
+impl PackageBackend for Homebrew {
+    fn name(&self) -> &'static str {
+        if self.is_cask() {
+            "cask"
+        } else {
+            "formula"
         }
-    } else {
-        println!("{}", "Failed to list packages".red());
     }
 
-    let cask_output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-        .args(["list", "--versions", "--cask"])
-        .output()?;
+    fn display_name(&self) -> &'static str {
+        if self.is_cask() {
+            "Casks"
+        } else {
+            "Formulae"
+        }
+    }
+
+    fn source(&self) -> &'static str {
+        "homebrew"
+    }
 
-    if cask_output.status.success() {
-        let packages = String::from_utf8_lossy(&cask_output.stdout);
-        println!("{}", "Casks:".cyan());
-        for package in packages.lines() {
-            println!("  {}", package);
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<PackageInfo>> {
+        // Third-party taps aren't part of the index, so look those up directly
+        if query.contains('/') {
+            return Ok(self.info(query).await?.into_iter().collect());
         }
+
+        let index = formula_index::load_index(&self.package_type).await?;
+        Ok(formula_index::fuzzy_search(index, query, limit)
+            .into_iter()
+            .map(|entry| PackageInfo {
+                name: entry.name,
+                version: entry.version,
+                description: entry.desc,
+            })
+            .collect())
+    }
+
+    async fn display_search_results(&self, query: &str, results: &[PackageInfo]) -> Result<()> {
+        if query.contains('/') {
+            if let Some(formula) = search_formula(query, Some(self.package_type.clone())).await? {
+                display_package_info(&formula, self.is_cask());
+            }
+        } else {
+            output::print_search_table(query, results);
+        }
+
         Ok(())
-    } else {
-        anyhow::bail!("{}", "Failed to list casks".red());
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        Ok(search_formula(name, Some(self.package_type.clone()))
+            .await?
+            .map(|formula| PackageInfo {
+                name: formula.name,
+                version: formula.versions.stable,
+                description: formula.desc,
+            }))
+    }
+
+    async fn versions(&self, name: &str) -> Result<Vec<String>> {
+        let Some(formula) = search_formula(name, Some(self.package_type.clone())).await? else {
+            return Ok(Vec::new());
+        };
+
+        let prefix = format!("{}@", formula.name);
+        let mut versions = formula
+            .versioned_formulae
+            .iter()
+            .filter_map(|versioned| versioned.strip_prefix(&prefix))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        versions.push(formula.versions.stable);
+        Ok(versions)
+    }
+
+    async fn install(&self, name: &str, version: Option<&str>) -> Result<()> {
+        let formula = search_formula(name, Some(self.package_type.clone()))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Package {} not found in Homebrew", name))?;
+
+        display_package_info(&formula, self.is_cask());
+
+        // Formulae fall back to homebrew-core history, casks only ship their latest version
+        if self.is_cask() && version.is_some_and(|v| v != formula.versions.stable) {
+            println!(
+                "\n{}",
+                "Note: Casks don't have version-specific installs available.".yellow()
+            );
+            println!(
+                "Installing latest version ({}) instead 🐕",
+                formula.versions.stable
+            );
+        }
+
+        install_formula_version(name, version, self.is_cask()).await
+    }
+
+    async fn uninstall(&self, name: &str) -> Result<()> {
+        uninstall_formula(name, self.is_cask()).await
+    }
+
+    /// Installing an older version only adds a versioned formula next to the current
+    /// keg, so the current version is uninstalled and the old one linked in its place.
+    async fn revert(&self, name: &str, version: &str) -> Result<()> {
+        if self.is_cask() {
+            anyhow::bail!(
+                "Casks only ship their latest version, {} can't be reverted to {}",
+                name,
+                version
+            );
+        }
+
+        let formula = search_formula(name, Some(HomebrewPackageType::Formula))
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Package {} not found in Homebrew", name))?;
+        let install_name = match formula.has_version(version) {
+            true => formula.get_install_name(Some(version)),
+            false => format!("{}/{}@{}", version_tap::VERSIONS_TAP, formula.name, version),
+        };
+        // The current release has no versioned formula, it replaces the newer keg instead
+        if install_name == formula.full_name {
+            uninstall_formula(name, false).await?;
+            return install_formula_version(name, Some(version), false).await;
+        }
+
+        // Install the old version first, so a failure leaves the current one in place
+        install_formula_version(name, Some(version), false).await?;
+        uninstall_formula(name, false).await?;
+
+        // Versioned formulae are usually keg-only, link them over the old name's files
+        let args = ["link", "--overwrite", "--force", install_name.as_str()];
+        if dry_run::is_enabled() {
+            dry_run::print_command(brew(), &args);
+            return Ok(());
+        }
+        let status = Command::new(brew()).args(args).status()?;
+        if !status.success() {
+            anyhow::bail!("Failed to link {}", install_name);
+        }
+        Ok(())
+    }
+
+    async fn uninstall_order(&self, packages: &[String]) -> Result<Vec<String>> {
+        if self.is_cask() || packages.is_empty() {
+            return Ok(packages.to_vec());
+        }
+
+        let mut args = vec!["--formula"];
+        args.extend(packages.iter().map(String::as_str));
+        let info = brew_info(&args)?;
+        Ok(in_dependency_order(&info.formulae)
+            .into_iter()
+            .rev()
+            .map(|formula| formula.name.clone())
+            .collect())
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        if dry_run::is_enabled() {
+            dry_run::print_command(brew(), &["update"]);
+            return Ok(());
+        }
+
+        println!("{}", "Updating Homebrew 🐕".cyan());
+        let status = Command::new(brew()).arg("update").status()?;
+
+        if !status.success() {
+            anyhow::bail!("Failed to update Homebrew");
+        }
+        println!("{}", "Homebrew updated successfully".green());
+        Ok(())
+    }
+
+    async fn set_pinned(&self, name: &str, pinned: bool) -> Result<()> {
+        // Homebrew can only pin formulae
+        if self.is_cask() {
+            return Ok(());
+        }
+
+        let args = [if pinned { "pin" } else { "unpin" }, name];
+        if dry_run::is_enabled() {
+            dry_run::print_command(brew(), &args);
+            return Ok(());
+        }
+
+        let output = Command::new(brew()).args(args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("{}", stderr.trim().trim_start_matches("Error: "));
+        }
+        Ok(())
+    }
+
+    async fn upgrade(&self, packages: &[String]) -> Result<()> {
+        if packages.is_empty() {
+            if let Err(e) = self.refresh().await {
+                println!("{}", e.to_string().red());
+                return Ok(());
+            }
+        }
+
+        let packages_to_update = if packages.is_empty() {
+            self.list()
+                .await?
+                .into_iter()
+                .map(|package| package.name)
+                .collect::<Vec<_>>()
+        } else {
+            packages.to_vec()
+        };
+
+        for package in packages_to_update {
+            println!("Updating {} 🐕", package.cyan());
+
+            let mut args = vec!["upgrade"];
+            if self.is_cask() {
+                args.push("--cask");
+            }
+            args.push(&package);
+
+            if dry_run::is_enabled() {
+                dry_run::print_command(brew(), &args);
+                continue;
+            }
+
+            let status = Command::new(brew()).args(&args).status()?;
+
+            if status.success() {
+                println!("{} updated successfully", package.green());
+            } else {
+                println!("Failed to update {}", package.red());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn outdated(&self) -> Result<Vec<OutdatedPackage>> {
+        // One index download is much cheaper than an API call per installed package
+        let index = formula_index::load_index(&self.package_type).await?;
+
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .filter_map(|package| {
+                let entry = index.iter().find(|entry| entry.name == package.name)?;
+                let latest = entry.full_version();
+                version::is_newer(&latest, &package.version).then_some(OutdatedPackage {
+                    name: package.name,
+                    current: package.version,
+                    latest,
+                })
+            })
+            .collect())
+    }
+
+    async fn list(&self) -> Result<Vec<InstalledPackage>> {
+        let output = Command::new(brew())
+            .args([
+                "list",
+                "--versions",
+                if self.is_cask() {
+                    "--cask"
+                } else {
+                    "--formula"
+                },
+            ])
+            .output()?;
+
+        if !output.status.success() {
+            anyhow::bail!("Failed to list {}", self.display_name().to_lowercase());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                // Several versions may be installed side by side; the newest is listed last
+                let mut parts = line.split_whitespace();
+                let name = parts.next()?;
+                Some(InstalledPackage {
+                    name: name.to_string(),
+                    version: parts.last().unwrap_or("").to_string(),
+                })
+            })
+            .collect())
     }
 }
@@ -0,0 +1,189 @@
+use crate::config::Config;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use colored::*;
+use dirs::home_dir;
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Metadata stored next to each cached response body
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    fetched_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    fn age_secs(&self) -> Option<u64> {
+        let fetched_at = DateTime::parse_from_rfc3339(&self.fetched_at).ok()?;
+        let age = Local::now().signed_duration_since(fetched_at);
+        u64::try_from(age.num_seconds()).ok()
+    }
+}
+
+/// When set, every request is answered from the cache and never touches the network.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Shared HTTP client so connections are reused between API calls.
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Fetches `url` through the on-disk cache. Returns `None` if the server answers 404.
+///
+/// Fresh entries (younger than the configured TTL) are returned as-is. Stale entries
+/// are revalidated with `If-None-Match` / `If-Modified-Since`, and served stale if the
+/// network is unreachable.
+pub async fn get(url: &str) -> Result<Option<Vec<u8>>> {
+    let (meta_path, body_path) = get_entry_paths(url)?;
+    let cached = read_entry(&meta_path).filter(|_| body_path.exists());
+
+    if is_offline() {
+        return match cached {
+            Some(_) => Ok(Some(fs::read(&body_path)?)),
+            None => anyhow::bail!("{} is not available in the offline cache", url),
+        };
+    }
+
+    let ttl = Config::load()?.cache_ttl_secs;
+    if let Some(entry) = &cached {
+        if entry.age_secs().is_some_and(|age| age < ttl) {
+            return Ok(Some(fs::read(&body_path)?));
+        }
+    }
+
+    let mut request = client().get(url).header(header::USER_AGENT, "bert");
+    if let Some(entry) = &cached {
+        if let Some(etag) = &entry.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(_) if cached.is_some() => {
+            eprintln!(
+                "{}",
+                format!("Network unavailable, using cached data for {}", url).yellow()
+            );
+            return Ok(Some(fs::read(&body_path)?));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    match response.status() {
+        StatusCode::NOT_MODIFIED if cached.is_some() => {
+            let mut entry = cached.unwrap();
+            entry.fetched_at = Local::now().to_rfc3339();
+            write_atomic(&meta_path, serde_json::to_string_pretty(&entry)?.as_bytes())?;
+            Ok(Some(fs::read(&body_path)?))
+        }
+        StatusCode::NOT_FOUND => Ok(None),
+        status if status.is_success() => {
+            let header_value = |name: header::HeaderName| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(String::from)
+            };
+            let entry = CacheEntry {
+                url: url.to_string(),
+                fetched_at: Local::now().to_rfc3339(),
+                etag: header_value(header::ETAG),
+                last_modified: header_value(header::LAST_MODIFIED),
+            };
+
+            // The body goes first, so the metadata never vouches for a body it doesn't describe
+            let body = response.bytes().await?.to_vec();
+            write_atomic(&body_path, &body)?;
+            write_atomic(&meta_path, serde_json::to_string_pretty(&entry)?.as_bytes())?;
+            Ok(Some(body))
+        }
+        // Server errors and rate limits shouldn't break what the cached data can still answer
+        status if cached.is_some() => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Request failed with status {}, using cached data for {}",
+                    status, url
+                )
+                .yellow()
+            );
+            Ok(Some(fs::read(&body_path)?))
+        }
+        status => anyhow::bail!("Request to {} failed with status {}", url, status),
+    }
+}
+
+fn read_entry(meta_path: &Path) -> Option<CacheEntry> {
+    let content = fs::read_to_string(meta_path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into place, so
+/// concurrent readers see either the old file or the new one, never a partial write.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path: {}", path.display()))?;
+    let temp_path = path.with_file_name(format!(
+        "{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    fs::write(&temp_path, contents)?;
+    if let Err(e) = fs::rename(&temp_path, path) {
+        fs::remove_file(&temp_path).ok();
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Cache files are named after a hash of the URL, so distinct URLs never share an entry.
+fn get_entry_paths(url: &str) -> Result<(PathBuf, PathBuf)> {
+    let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+
+    let cache_dir = get_cache_dir()?;
+    Ok((
+        cache_dir.join(format!("{}.meta.json", key)),
+        cache_dir.join(format!("{}.body", key)),
+    ))
+}
+
+pub fn get_cache_dir() -> Result<PathBuf> {
+    let cache_dir = home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?
+        .join(".bert")
+        .join("cache");
+
+    if !cache_dir.exists() {
+        fs::create_dir_all(&cache_dir)?;
+    }
+
+    Ok(cache_dir)
+}
@@ -0,0 +1,473 @@
+use crate::backend::PackageBackend;
+use crate::config::Config;
+use crate::homebrew::{self, brew, FormulaInfo};
+use crate::node::{self, types::NpmPackageInfo, NodeManager};
+use crate::{dry_run, http_cache, tap};
+use anyhow::Result;
+use chrono::Local;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Bumped whenever the lockfile format changes incompatibly
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Homebrew's bottles on ghcr.io are public, but the registry still expects a bearer token
+const GHCR_ANONYMOUS_TOKEN: &str = "QQ==";
+
+/// The `bert.lock` format: exact versions and checksums of everything installed, written
+/// next to each backup so `bert restore --locked` can reproduce a machine exactly.
+#[derive(Serialize, Deserialize)]
+pub struct Lockfile {
+    pub lockfile_version: u32,
+    pub created_at: String,
+    pub taps: Vec<LockedTap>,
+    /// Ordered so that dependencies come before the formulas that need them
+    pub formulas: Vec<LockedFormula>,
+    pub casks: Vec<LockedCask>,
+    #[serde(default)]
+    pub node_packages: Vec<LockedNodePackage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedTap {
+    pub name: String,
+    /// Commit the tap was at, if it's a git checkout
+    pub commit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedFormula {
+    pub name: String,
+    pub version: String,
+    /// Bottle the formula was poured from, if it was poured from the current one
+    pub bottle: Option<LockedBottle>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedBottle {
+    /// Platform tag, e.g. `arm64_sonoma` or `all`
+    pub tag: String,
+    #[serde(default)]
+    pub rebuild: u32,
+    pub url: String,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedCask {
+    pub name: String,
+    pub version: String,
+    /// Download checksum, unless the cask doesn't pin one
+    pub sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LockedNodePackage {
+    pub name: String,
+    pub version: String,
+    /// Subresource integrity of the published tarball
+    pub integrity: Option<String>,
+}
+
+/// The lockfile belonging to a backup, e.g. `bert_backup_20240101_120000.lock`.
+pub fn path_for(backup_path: &Path) -> PathBuf {
+    backup_path.with_extension("lock")
+}
+
+pub fn load(path: &Path) -> Result<Lockfile> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Could not read lockfile {}: {}", path.display(), e))?;
+    let lockfile: Lockfile = serde_json::from_str(&content)?;
+    if lockfile.lockfile_version > LOCKFILE_VERSION {
+        anyhow::bail!(
+            "{} was written by a newer bert (lockfile version {}), please update bert",
+            path.display(),
+            lockfile.lockfile_version
+        );
+    }
+    Ok(lockfile)
+}
+
+pub fn save(lockfile: &Lockfile, path: &Path) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(lockfile)?)?;
+    Ok(())
+}
+
+/// Records the exact state of `taps` and of every installed formula, cask and global
+/// Node.js package.
+pub async fn generate(taps: &[String]) -> Result<Lockfile> {
+    let commits = tap::head_commits(taps)?;
+    let taps = taps
+        .iter()
+        .map(|name| LockedTap {
+            name: name.clone(),
+            commit: commits.get(name).cloned(),
+        })
+        .collect();
+
+    let info = homebrew::brew_info(&["--installed"])?;
+    let tag = bottle_tag(&info.formulae);
+    let formulas = homebrew::in_dependency_order(&info.formulae)
+        .into_iter()
+        .filter_map(|formula| lock_formula(formula, tag.as_deref()))
+        .collect();
+
+    let casks = info
+        .casks
+        .into_iter()
+        .filter_map(|cask| {
+            let installed = cask.installed?;
+            // Only the current version's checksum is known
+            let sha256 = cask
+                .sha256
+                .filter(|sha256| installed == cask.version && sha256 != "no_check");
+            Some(LockedCask {
+                name: cask.token,
+                version: installed,
+                sha256,
+            })
+        })
+        .collect();
+
+    Ok(Lockfile {
+        lockfile_version: LOCKFILE_VERSION,
+        created_at: Local::now().to_rfc3339(),
+        taps,
+        formulas,
+        casks,
+        node_packages: lock_node_packages().await?,
+    })
+}
+
+fn lock_formula(formula: &FormulaInfo, tag: Option<&str>) -> Option<LockedFormula> {
+    let keg = formula.installed.last()?;
+
+    // The API only describes the current bottle, an outdated keg's bottle is unknown
+    let bottle = formula
+        .bottle
+        .get("stable")
+        .filter(|_| keg.poured_from_bottle && keg.version == formula.pkg_version())
+        .and_then(|spec| {
+            [tag, Some("all")]
+                .into_iter()
+                .flatten()
+                .find_map(|tag| spec.files.get_key_value(tag))
+                .map(|(tag, file)| LockedBottle {
+                    tag: tag.clone(),
+                    rebuild: spec.rebuild,
+                    url: file.url.clone(),
+                    sha256: file.sha256.clone(),
+                })
+        });
+
+    Some(LockedFormula {
+        name: formula.name.clone(),
+        version: keg.version.clone(),
+        bottle,
+    })
+}
+
+/// Homebrew's bottle tag for this machine, read off the cache path of a poured bottle,
+/// e.g. `.../jq--1.7.1.arm64_sonoma.bottle.tar.gz`.
+fn bottle_tag(formulae: &[FormulaInfo]) -> Option<String> {
+    formulae
+        .iter()
+        .filter(|formula| formula.installed.iter().any(|keg| keg.poured_from_bottle))
+        .find_map(|formula| {
+            let output = Command::new(brew())
+                .args(["--cache", "--force-bottle", &formula.name])
+                .output()
+                .ok()?;
+            let path = String::from_utf8_lossy(&output.stdout);
+            let prefix = format!("{}--{}.", formula.name, formula.pkg_version());
+            let (_, rest) = path.trim().split_once(&prefix)?;
+            let tag = rest.split(".bottle").next()?;
+
+            // `all` bottles don't tell us anything about this machine
+            (tag != "all").then(|| tag.to_string())
+        })
+}
+
+async fn lock_node_packages() -> Result<Vec<LockedNodePackage>> {
+    let manager = NodeManager::new(Config::load()?.get_node_package_manager()?);
+    // No package manager installed just means there's nothing to lock
+    let packages = manager.list_packages().await.unwrap_or_default();
+
+    let mut locked = Vec::new();
+    for package in packages {
+        let info = node::get_package_info(&package.name).await.ok().flatten();
+        locked.push(LockedNodePackage {
+            integrity: info.and_then(|info| integrity_of(&info, &package.version)),
+            name: package.name,
+            version: package.version,
+        });
+    }
+    Ok(locked)
+}
+
+fn integrity_of(info: &NpmPackageInfo, version: &str) -> Option<String> {
+    info.versions
+        .get(version)?
+        .get("dist")?
+        .get("integrity")?
+        .as_str()
+        .map(String::from)
+}
+
+/// Installs exactly what `lockfile` records. Entries that can't be reproduced are
+/// reported with the reason instead of falling back to another version.
+pub async fn restore(lockfile: &Lockfile) -> Result<()> {
+    let mut failures = Vec::new();
+
+    println!("\n{}:", "Restoring taps".cyan());
+    let installed_taps = tap::list_taps()?;
+    let mut checkouts = Vec::new();
+    for locked in &lockfile.taps {
+        let result = restore_tap(locked, &installed_taps).map(|checkout| {
+            checkouts.extend(checkout);
+        });
+        report(&locked.name, result, &mut failures);
+    }
+
+    let result = restore_formulas_and_casks(lockfile, &mut failures).await;
+
+    // Formulae and casks are installed, so the taps can follow `brew update` again
+    for checkout in checkouts {
+        let tap = checkout.tap.clone();
+        if let Err(e) = checkout.restore() {
+            println!("  {} {}: {}", "✗".red(), tap, e);
+            failures.push((tap, e.to_string()));
+        }
+    }
+    result?;
+
+    if !lockfile.node_packages.is_empty() {
+        println!("\n{}:", "Restoring Node.js packages".cyan());
+        let manager = NodeManager::new(Config::load()?.get_node_package_manager()?);
+        let installed = manager.list_packages().await.unwrap_or_default();
+        for locked in &lockfile.node_packages {
+            let result = match installed
+                .iter()
+                .any(|package| package.name == locked.name && package.version == locked.version)
+            {
+                true => Ok(()),
+                false => restore_node_package(&manager, locked).await,
+            };
+            report(&locked.name, result, &mut failures);
+        }
+    }
+
+    if !failures.is_empty() {
+        println!(
+            "\n{}",
+            format!("{} entries could not be restored exactly:", failures.len()).red()
+        );
+        for (name, reason) in &failures {
+            println!("  {:<40} {}", name, reason);
+        }
+        anyhow::bail!("Locked restore incomplete");
+    }
+    Ok(())
+}
+
+async fn restore_formulas_and_casks(
+    lockfile: &Lockfile,
+    failures: &mut Vec<(String, String)>,
+) -> Result<()> {
+    println!("\n{}:", "Restoring formulas".cyan());
+    let installed = installed_versions(&["list", "--formula", "--versions"])?;
+    for locked in &lockfile.formulas {
+        let result = match is_installed(&installed, &locked.name, &locked.version) {
+            true => Ok(()),
+            false => restore_formula(locked).await,
+        };
+        report(&locked.name, result, failures);
+    }
+
+    println!("\n{}:", "Restoring casks".cyan());
+    let installed = installed_versions(&["list", "--cask", "--versions"])?;
+    for locked in &lockfile.casks {
+        let result = match is_installed(&installed, &locked.name, &locked.version) {
+            true => Ok(()),
+            false => restore_cask(locked),
+        };
+        report(&locked.name, result, failures);
+    }
+    Ok(())
+}
+
+fn report(label: &str, result: Result<()>, failures: &mut Vec<(String, String)>) {
+    match result {
+        Ok(()) => println!("  {:<40} {}", label, "✓".green()),
+        Err(e) => {
+            println!("  {:<40} {} {}", label, "✗".red(), e);
+            failures.push((label.to_string(), e.to_string()));
+        }
+    }
+}
+
+fn restore_tap(locked: &LockedTap, installed_taps: &[String]) -> Result<Option<tap::Checkout>> {
+    if !installed_taps.contains(&locked.name) {
+        run_brew(&["tap", &locked.name])?;
+    }
+    locked
+        .commit
+        .as_ref()
+        .map(|commit| tap::checkout_commit(&locked.name, commit))
+        .transpose()
+}
+
+async fn restore_formula(locked: &LockedFormula) -> Result<()> {
+    if let Some(bottle) = &locked.bottle {
+        let path = download_bottle(locked, bottle).await?;
+        return run_brew(&["install", &path.to_string_lossy()]);
+    }
+
+    // Without a locked bottle, only the version Homebrew currently ships can be reproduced
+    let current = homebrew::brew_info(&["--formula", &locked.name])?
+        .formulae
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no longer available from Homebrew"))?;
+    if current.pkg_version() != locked.version {
+        anyhow::bail!(
+            "no bottle was locked and Homebrew now ships {} instead of {}",
+            current.pkg_version(),
+            locked.version
+        );
+    }
+    run_brew(&["install", "--formula", &locked.name])
+}
+
+/// Downloads a locked bottle into the cache and checks it against the locked checksum.
+async fn download_bottle(locked: &LockedFormula, bottle: &LockedBottle) -> Result<PathBuf> {
+    let dir = http_cache::get_cache_dir()?.join("bottles");
+    fs::create_dir_all(&dir)?;
+
+    // brew only installs bottle files that are named the way it names them
+    let rebuild = match bottle.rebuild {
+        0 => String::new(),
+        rebuild => format!(".{}", rebuild),
+    };
+    let path = dir.join(format!(
+        "{}--{}.{}.bottle{}.tar.gz",
+        locked.name, locked.version, bottle.tag, rebuild
+    ));
+
+    if dry_run::is_enabled() {
+        println!(
+            "  {} download {} and verify sha256 {}",
+            "[dry-run]".yellow(),
+            bottle.url,
+            bottle.sha256
+        );
+        return Ok(path);
+    }
+    if http_cache::is_offline() {
+        anyhow::bail!("the locked bottle can't be downloaded while offline");
+    }
+
+    let mut request = http_cache::client().get(&bottle.url);
+    if bottle.url.starts_with("https://ghcr.io/") {
+        request = request.bearer_auth(GHCR_ANONYMOUS_TOKEN);
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        anyhow::bail!("bottle download failed with HTTP {}", response.status());
+    }
+
+    let bytes = response.bytes().await?;
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+    if sha256 != bottle.sha256 {
+        anyhow::bail!(
+            "bottle checksum mismatch, locked {} but downloaded {}",
+            bottle.sha256,
+            sha256
+        );
+    }
+
+    fs::write(&path, &bytes)?;
+    Ok(path)
+}
+
+fn restore_cask(locked: &LockedCask) -> Result<()> {
+    // Homebrew only ever ships the current version of a cask
+    let current = homebrew::brew_info(&["--cask", &locked.name])?
+        .casks
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no longer available from Homebrew"))?;
+    if current.version != locked.version {
+        anyhow::bail!(
+            "Homebrew now ships {} instead of {}",
+            current.version,
+            locked.version
+        );
+    }
+    if let Some(sha256) = &locked.sha256 {
+        if current.sha256.as_ref() != Some(sha256) {
+            anyhow::bail!("the download checksum changed since it was locked");
+        }
+    }
+    run_brew(&["install", "--cask", &locked.name])
+}
+
+async fn restore_node_package(manager: &NodeManager, locked: &LockedNodePackage) -> Result<()> {
+    let info = node::get_package_info(&locked.name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("not found in the npm registry"))?;
+    if !info.versions.contains_key(&locked.version) {
+        anyhow::bail!("version {} is no longer published", locked.version);
+    }
+
+    // The package manager checks the tarball against the registry's integrity hash
+    if let Some(integrity) = &locked.integrity {
+        if integrity_of(&info, &locked.version).as_ref() != Some(integrity) {
+            anyhow::bail!("the published integrity hash changed since it was locked");
+        }
+    }
+    manager.install(&locked.name, Some(&locked.version)).await
+}
+
+/// Installed versions by name, from `brew list --versions` output.
+fn installed_versions(args: &[&str]) -> Result<HashMap<String, Vec<String>>> {
+    let output = Command::new(brew()).args(args).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?.to_string();
+            Some((name, parts.map(String::from).collect()))
+        })
+        .collect())
+}
+
+fn is_installed(installed: &HashMap<String, Vec<String>>, name: &str, version: &str) -> bool {
+    installed
+        .get(name)
+        .is_some_and(|versions| versions.iter().any(|v| v == version))
+}
+
+/// Runs `brew <args>` quietly, failing with brew's last error line.
+fn run_brew(args: &[&str]) -> Result<()> {
+    if dry_run::is_enabled() {
+        dry_run::print_command(brew(), args);
+        return Ok(());
+    }
+
+    let output = Command::new(brew())
+        .args(args)
+        // Keep brew from moving taps off their locked commits in the middle of the restore
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .output()?;
+    if !output.status.success() {
+        let reason = crate::output::error_line(&output.stderr);
+        anyhow::bail!("{}", reason.as_deref().unwrap_or("brew failed"));
+    }
+    Ok(())
+}
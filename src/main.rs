@@ -1,16 +1,38 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::*;
+use output::OutputFormat;
+use package_manager::PackageRequest;
+use spec::PackageSpec;
 
 // Import our local modules
+mod backend;
 mod backup_manager;
+mod bertfile;
 mod command_handler;
 mod config;
+mod dry_run;
+mod ephemeral;
+mod executable_index;
+mod formula_index;
+mod history;
 mod homebrew;
+mod http_cache;
+mod lockfile;
 mod node;
+mod outdated;
+mod output;
 mod package_manager;
 mod platform;
+mod prompt;
+mod restore_jobs;
 mod self_update;
+mod shell_init;
+mod spec;
+mod tap;
+mod version;
+mod version_tap;
 
 #[derive(Parser)]
 #[command(
@@ -26,48 +48,110 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Install as a cask application
+    #[command(flatten)]
+    backend: BackendFlags,
+
+    /// Answer metadata lookups from the local cache only
     #[arg(long, global = true)]
-    cask: bool,
+    offline: bool,
 
-    /// Install a node package globally
+    /// Print what would be installed, removed or updated without changing anything
     #[arg(long, global = true)]
-    node: bool,
+    dry_run: bool,
+
+    /// Answer yes to confirmation prompts, e.g. when auto-installing a missing command
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
 
     /// Command to execute if no subcommand is provided
     #[arg(trailing_var_arg = true)]
     args: Vec<String>,
 }
 
+/// Selects the backend a command works on. Subcommands take these flags themselves rather
+/// than as global flags, so `install` can tell which packages a flag was given before.
+#[derive(Args, Clone, Copy, Default)]
+struct BackendFlags {
+    /// Use Homebrew casks
+    #[arg(long)]
+    cask: bool,
+
+    /// Use global node packages
+    #[arg(long)]
+    node: bool,
+}
+
+impl BackendFlags {
+    /// Combines flags given before the subcommand with the subcommand's own.
+    fn or(self, other: Self) -> Self {
+        Self {
+            cask: self.cask || other.cask,
+            node: self.node || other.node,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Install a package
+    /// Install one or more packages
     Install {
-        /// Name of the package to install
-        package: String,
+        /// Packages to install, optionally with a version (name@version).
+        /// `--cask` / `--node` apply to the packages that follow them
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Output format for the --dry-run plan
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+        #[command(flatten)]
+        backend: BackendFlags,
     },
     /// Search for a package
     Search {
         /// Name of the package to search for
         query: String,
+        /// Maximum number of results to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+        #[command(flatten)]
+        backend: BackendFlags,
     },
     /// Update installed packages
     Update {
         /// Optional package names to update
         #[arg(trailing_var_arg = true)]
         packages: Vec<String>,
+        #[command(flatten)]
+        backend: BackendFlags,
     },
     /// Uninstall a package
     Uninstall {
         /// Name of the package to uninstall
         package: String,
+        #[command(flatten)]
+        backend: BackendFlags,
     },
     /// List installed packages
-    List,
+    List {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+        #[command(flatten)]
+        backend: BackendFlags,
+    },
+    /// Show installed packages that have newer versions available
+    Outdated {
+        #[command(flatten)]
+        backend: BackendFlags,
+    },
     /// Update bert to the latest version
     SelfUpdate,
     /// Create a backup of installed formulas and casks
     Backup {
+        #[command(subcommand)]
+        command: Option<BackupCommands>,
         /// Optional custom path for the backup file
         #[arg(short, long)]
         output: Option<String>,
@@ -77,12 +161,116 @@ enum Commands {
         /// Optional path to the backup file (uses latest backup if not specified)
         #[arg(short, long)]
         input: Option<String>,
+        /// Install the exact versions and artifacts recorded in the backup's lockfile
+        #[arg(long, conflicts_with = "resume")]
+        locked: bool,
+        /// Continue an interrupted restore, skipping what it already installed
+        #[arg(long)]
+        resume: bool,
+        /// Number of installs to run at once (defaults to restore_jobs in the config)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Install everything listed in a Bertfile
+    Sync {
+        /// Path to the Bertfile
+        #[arg(short, long, default_value = "Bertfile")]
+        file: String,
+        /// Also remove packages and taps that aren't listed
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Pin a package so `update` skips it (lists pins when no package is given)
+    Pin {
+        /// Package to pin, optionally at a version (name@version)
+        package: Option<String>,
+        #[command(flatten)]
+        backend: BackendFlags,
+    },
+    /// Remove a pin so `update` upgrades the package again
+    Unpin {
+        /// Name of the package to unpin
+        package: String,
+        #[command(flatten)]
+        backend: BackendFlags,
+    },
+    /// Show the log of installs, uninstalls, updates and restores
+    History {
+        /// Number of most recent transactions to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Reverse a transaction from the history (the latest one by default)
+    Undo {
+        /// Id of the transaction to undo
+        id: Option<u64>,
+    },
+    /// Run a package once without installing it globally
+    Run {
+        /// Package to run, optionally with a version (name@version)
+        package: String,
+        /// Executable to run when the package provides several
+        #[arg(long)]
+        bin: Option<String>,
+        /// Arguments passed to the executable, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+        #[command(flatten)]
+        backend: BackendFlags,
+    },
+    /// Manage Homebrew taps
+    Tap {
+        #[command(subcommand)]
+        command: TapCommands,
+    },
+    /// Print a shell hook that offers to install missing commands
+    ShellInit {
+        /// Shell to generate the hook for
+        #[arg(value_enum)]
+        shell: shell_init::Shell,
+    },
+    /// Used by the shell hook when a command isn't found
+    #[command(hide = true)]
+    CommandNotFound {
+        /// The missing command
+        command: String,
     },
     SetManager {
         /// Package manager to use (npm, yarn, or pnpm)
         manager: String,
     },
 }
+
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// Check a backup file for schema problems and unknown packages
+    Validate {
+        /// Path to the backup file
+        file: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TapCommands {
+    /// Tap a repository and trust it for implicit taps
+    Add {
+        /// Tap name (user/repo)
+        name: String,
+    },
+    /// Untap a repository and stop trusting it
+    Remove {
+        /// Tap name (user/repo)
+        name: String,
+    },
+    /// List installed taps and whether they are trusted
+    List,
+    /// Show a tap's formula count and last update
+    Info {
+        /// Tap name (user/repo)
+        name: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Enable colored output on Windows
@@ -92,7 +280,11 @@ async fn main() -> Result<()> {
     // Load config at startup
     let mut config = config::Config::load()?;
 
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+    http_cache::set_offline(cli.offline);
+    dry_run::set_enabled(cli.dry_run);
+    prompt::set_assume_yes(cli.yes);
 
     match cli.command {
         Some(Commands::SetManager { manager }) => {
@@ -100,46 +292,121 @@ async fn main() -> Result<()> {
             config.set_node_package_manager(npm_manager)?;
             println!("Package manager set to: {}", manager.green());
         }
-        Some(Commands::Backup { output }) => {
-            backup_manager::create_backup(output.as_deref()).await?;
+        Some(Commands::Backup { command, output }) => match command {
+            Some(BackupCommands::Validate { file }) => {
+                backup_manager::validate_backup(&file).await?;
+            }
+            None => backup_manager::create_backup(output.as_deref()).await?,
+        },
+        Some(Commands::Restore {
+            input,
+            locked,
+            resume,
+            jobs,
+        }) => {
+            backup_manager::restore_backup(input.as_deref(), locked, resume, jobs).await?;
+        }
+        Some(Commands::Sync { file, prune }) => {
+            bertfile::sync(&file, prune).await?;
+        }
+        Some(Commands::Pin { package, backend }) => match package {
+            Some(package) => {
+                let backend = cli.backend.or(backend);
+                package_manager::pin_package(&package, backend.cask, backend.node).await?
+            }
+            None => package_manager::list_pins()?,
+        },
+        Some(Commands::Unpin { package, backend }) => {
+            let backend = cli.backend.or(backend);
+            package_manager::unpin_package(&package, backend.cask, backend.node).await?;
+        }
+        Some(Commands::History { limit }) => {
+            history::show_history(limit)?;
         }
-        Some(Commands::Restore { input }) => {
-            backup_manager::restore_backup(input.as_deref()).await?;
+        Some(Commands::Undo { id }) => {
+            history::undo(id).await?;
+        }
+        Some(Commands::Run {
+            package,
+            bin,
+            args,
+            backend,
+        }) => {
+            let backend = cli.backend.or(backend);
+            if backend.cask {
+                anyhow::bail!("Casks can't be run with bert run");
+            }
+            let status =
+                command_handler::run_ephemeral(&package, backend.node, bin.as_deref(), &args)
+                    .await?;
+            command_handler::exit_like(status);
+        }
+        Some(Commands::Tap { command }) => match command {
+            TapCommands::Add { name } => tap::add_tap(&name)?,
+            TapCommands::Remove { name } => tap::remove_tap(&name)?,
+            TapCommands::List => tap::show_taps()?,
+            TapCommands::Info { name } => tap::show_tap_info(&name)?,
+        },
+        Some(Commands::ShellInit { shell }) => {
+            shell_init::print_hook(shell)?;
+        }
+        Some(Commands::CommandNotFound { command }) => {
+            if !command_handler::offer_install(&command).await? {
+                std::process::exit(127);
+            }
+        }
+        Some(Commands::Outdated { backend }) => {
+            let backend = cli.backend.or(backend);
+            outdated::show_outdated(backend.cask, backend.node).await?;
         }
         Some(Commands::SelfUpdate) => {
             self_update::self_update().await?;
         }
-        Some(Commands::Uninstall { package }) => {
-            package_manager::uninstall_package(&package, cli.cask, cli.node).await?;
+        Some(Commands::Uninstall { package, backend }) => {
+            let backend = cli.backend.or(backend);
+            package_manager::uninstall_package(&package, backend.cask, backend.node).await?;
         }
-        Some(Commands::Install { package }) => {
-            // Parse package name and version
-            let (name, version) = parse_package_spec(&package);
-            println!("Installing package: {} 🐕", name.cyan());
-            if let Some(ver) = version {
-                println!("Version: {}", ver.cyan());
-            }
+        Some(Commands::Install {
+            packages, output, ..
+        }) => {
+            let install_matches = matches
+                .subcommand_matches("install")
+                .expect("install subcommand matches");
+            let requests = package_requests(&packages, cli.backend, install_matches);
+            dry_run::set_print_to_stderr(output.is_structured());
 
-            package_manager::install_package_version(name, version, cli.cask, cli.node)
+            package_manager::install_packages(&requests, output)
                 .await
-                .with_context(|| format!("Failed to install package: {}", package))?;
+                .with_context(|| format!("Failed to install package: {}", packages.join(" ")))?;
         }
-        Some(Commands::Search { query }) => {
-            println!("Searching for packages matching: {} 🐕", query.cyan());
-            package_manager::search_package(&query, cli.cask, cli.node).await?;
+        Some(Commands::Search {
+            query,
+            limit,
+            output,
+            backend,
+        }) => {
+            let backend = cli.backend.or(backend);
+            dry_run::set_print_to_stderr(output.is_structured());
+            if !output.is_structured() {
+                println!("Searching for packages matching: {} 🐕", query.cyan());
+            }
+            package_manager::search_package(&query, limit, backend.cask, backend.node, output)
+                .await?;
         }
-        Some(Commands::Update { packages }) => {
-            crate::package_manager::update_packages(&packages, cli.node).await?;
+        Some(Commands::Update { packages, backend }) => {
+            let backend = cli.backend.or(backend);
+            package_manager::update_packages(&packages, backend.cask, backend.node).await?;
         }
-        Some(Commands::List) => {
-            println!("{}", "Installed packages:".cyan());
-            package_manager::list_packages(cli.node).await?;
+        Some(Commands::List { output, backend }) => {
+            let backend = cli.backend.or(backend);
+            package_manager::list_packages(backend.cask, backend.node, output).await?;
         }
         None => {
             if !cli.args.is_empty() {
-                command_handler::handle_command(&cli.args)
+                let status = command_handler::handle_command(&cli.args)
                     .await
                     .with_context(|| format!("Failed to execute command: {}", cli.args[0]))?;
+                command_handler::exit_like(status);
             } else {
                 println!("No command specified. Use --help for usage information.");
             }
@@ -149,11 +416,52 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn parse_package_spec(spec: &str) -> (&str, Option<&str>) {
-    if let Some(idx) = spec.find('@') {
-        let (name, version) = spec.split_at(idx);
-        (name, Some(&version[1..]))
-    } else {
-        (spec, None)
-    }
+/// Pairs each install spec with a backend. A `--cask` or `--node` flag applies to the
+/// packages after it (`bert install jq fd --node typescript`). A flag given before the
+/// subcommand (`leading`) or after every package applies to all of them.
+fn package_requests(
+    packages: &[String],
+    leading: BackendFlags,
+    matches: &ArgMatches,
+) -> Vec<PackageRequest> {
+    let package_indices = matches
+        .indices_of("packages")
+        .map(|indices| indices.collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // Returns the flag's position among the install arguments, and whether it covers every package
+    let flag_position = |id: &str, leading: bool| -> (Option<usize>, bool) {
+        if matches.value_source(id) != Some(ValueSource::CommandLine) {
+            return (None, leading);
+        }
+        let index = matches.index_of(id);
+        let after_all = index.is_some_and(|flag| package_indices.iter().all(|&i| i < flag));
+        (index, leading || after_all)
+    };
+    let (node_index, node_for_all) = flag_position("node", leading.node);
+    let (cask_index, cask_for_all) = flag_position("cask", leading.cask);
+
+    packages
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            let index = package_indices.get(i).copied().unwrap_or(0);
+            let before = |flag: Option<usize>| flag.filter(|&flag| flag < index);
+
+            // The flag closest before the package wins
+            let backend = match (before(node_index), before(cask_index)) {
+                (Some(node), Some(cask)) if cask > node => "cask",
+                (Some(_), _) => "node",
+                (None, Some(_)) => "cask",
+                (None, None) => backend::backend_name(cask_for_all, node_for_all),
+            };
+
+            let spec = PackageSpec::parse(spec);
+            PackageRequest {
+                name: spec.name,
+                version: spec.version,
+                backend,
+            }
+        })
+        .collect()
 }
@@ -1,17 +1,15 @@
 use super::types::*;
+use crate::config::Config;
+use crate::http_cache;
 use anyhow::Result;
 use colored::*;
-use reqwest;
 
 pub async fn get_package_info(name: &str) -> Result<Option<NpmPackageInfo>> {
-    let client = reqwest::Client::new();
-    let url = format!("https://registry.npmjs.org/{}", name);
-    let response = client.get(&url).send().await?;
+    let url = format!("{}/{}", Config::load()?.npm_registry_url(), name);
 
-    if response.status().is_success() {
-        Ok(Some(response.json().await?))
-    } else {
-        Ok(None)
+    match http_cache::get(&url).await? {
+        Some(body) => Ok(Some(serde_json::from_slice(&body)?)),
+        None => Ok(None),
     }
 }
 
@@ -1,8 +1,12 @@
 use super::types::*;
+use crate::backend::{InstalledPackage, PackageBackend, PackageInfo};
+use crate::spec::PackageSpec;
 use anyhow::Result;
+use async_trait::async_trait;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::{process::Command, thread, time::Duration};
+use std::path::PathBuf;
+use std::{fs, process::Command, time::Duration};
 
 pub struct NodeManager {
     package_manager: NodePackageManager,
@@ -13,43 +17,90 @@ impl NodeManager {
         Self { package_manager }
     }
 
-    pub async fn install_package(&self, name: &str, version: Option<&str>) -> Result<()> {
+    /// Installs a global package, with a spinner unless `show_progress` is off.
+    pub async fn install_package(
+        &self,
+        name: &str,
+        version: Option<&str>,
+        show_progress: bool,
+    ) -> Result<()> {
         if !self.is_node_installed() {
             println!("Node.js is required. Installing Node.js first...");
             // Use homebrew module to install node
             crate::homebrew::install_formula_version("node", None, false).await?;
         }
 
-        let mut args = self.package_manager.install_args();
-        let package_with_version = match version {
-            Some(v) => format!("{}@{}", name, v),
-            None => name.to_string(),
+        let spec = PackageSpec {
+            name: name.to_string(),
+            version: version.map(String::from),
         };
+        let mut args = self.package_manager.install_args();
+        let package_with_version = spec.to_string();
         args.push(&package_with_version);
 
-        println!(
-            "Installing {} via {}...",
-            package_with_version.cyan(),
-            self.package_manager.command()
-        );
+        if show_progress {
+            println!(
+                "Installing {} via {}...",
+                package_with_version.cyan(),
+                self.package_manager.command()
+            );
+        }
+
+        if crate::dry_run::is_enabled() {
+            // Git, tarball and path specs are resolved by the package manager itself
+            let info = match spec.is_direct() {
+                true => None,
+                false => super::get_package_info(name).await?,
+            };
+            if let Some(info) = info {
+                let tag = version.unwrap_or("latest");
+                let resolved = info
+                    .dist_tags
+                    .as_ref()
+                    .and_then(|tags| tags.get(tag))
+                    .and_then(|version| version.as_str())
+                    .unwrap_or(tag);
+                println!("  Resolved {} to version {}", name.cyan(), resolved.green());
+            }
+            crate::dry_run::print_command(self.package_manager.command(), &args);
+            return Ok(());
+        }
+
+        // Async so that parallel installs don't each hold a runtime thread while they wait
+        let mut command = tokio::process::Command::new(self.package_manager.command());
+        command.args(&args).stdout(std::process::Stdio::piped());
+
+        if !show_progress {
+            // Keep the output of installs running side by side from interleaving
+            let output = command
+                .stderr(std::process::Stdio::piped())
+                .output()
+                .await?;
+            if output.status.success() {
+                return Ok(());
+            }
+            let reason = crate::output::error_line(&output.stderr);
+            anyhow::bail!(
+                "Failed to install {}: {}",
+                name,
+                reason.as_deref().unwrap_or("exited with an error")
+            );
+        }
 
         let progress_bar = ProgressBar::new(100);
-        let mut child = Command::new(self.package_manager.command())
-            .args(&args)
-            .stdout(std::process::Stdio::piped())
-            .spawn()?;
+        let mut child = command.spawn()?;
 
         // Create a simple spinner style
         progress_bar.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}"));
         progress_bar.set_message(&format!("Installing {}", name));
 
-        while child.try_wait()?.is_none() {
-            progress_bar.tick();
-            thread::sleep(Duration::from_millis(100));
-        }
-
-        // Just wait for the process to complete
-        let status = child.wait()?;
+        let mut ticks = tokio::time::interval(Duration::from_millis(100));
+        let status = loop {
+            tokio::select! {
+                status = child.wait() => break status?,
+                _ = ticks.tick() => progress_bar.tick(),
+            }
+        };
 
         if status.success() {
             progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
@@ -58,7 +109,7 @@ impl NodeManager {
                 "✔".green(),
                 name
             ));
-            return Ok(());
+            Ok(())
         } else {
             progress_bar.set_style(ProgressStyle::default_spinner().template("{msg}"));
             progress_bar.finish_with_message(&format!("{} Failed to install {}", "✘".red(), name));
@@ -76,6 +127,11 @@ impl NodeManager {
             self.package_manager.command()
         );
 
+        if crate::dry_run::is_enabled() {
+            crate::dry_run::print_command(self.package_manager.command(), &args);
+            return Ok(());
+        }
+
         let status = Command::new(self.package_manager.command())
             .args(&args)
             .status()?;
@@ -97,6 +153,11 @@ impl NodeManager {
             self.package_manager.command()
         );
 
+        if crate::dry_run::is_enabled() {
+            crate::dry_run::print_command(self.package_manager.command(), &args);
+            return Ok(());
+        }
+
         let status = Command::new(self.package_manager.command())
             .args(&args)
             .status()?;
@@ -109,19 +170,19 @@ impl NodeManager {
         Ok(())
     }
 
-    pub async fn list_packages(&self) -> Result<()> {
+    pub async fn list_packages(&self) -> Result<Vec<InstalledPackage>> {
+        let Some(args) = self.package_manager.list_args() else {
+            return list_bun_globals();
+        };
+
+        // npm exits non-zero for problems like extraneous packages but still lists them
         let output = Command::new(self.package_manager.command())
-            .args(self.package_manager.list_args())
+            .args(args)
             .output()?;
 
-        if output.status.success() {
-            let packages = String::from_utf8_lossy(&output.stdout);
-            for package in packages.lines().skip(1) {
-                println!("  {}", package);
-            }
-        }
-
-        Ok(())
+        Ok(self
+            .package_manager
+            .parse_list_output(&String::from_utf8_lossy(&output.stdout)))
     }
 
     pub fn is_node_installed(&self) -> bool {
@@ -132,3 +193,103 @@ impl NodeManager {
             .unwrap_or(false)
     }
 }
+
+/// Bun keeps its globals in `$BUN_INSTALL/install/global`, a regular package with the
+/// installed versions in its `node_modules`.
+fn list_bun_globals() -> Result<Vec<InstalledPackage>> {
+    let root = match std::env::var_os("BUN_INSTALL") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?
+            .join(".bun"),
+    };
+    let global = root.join("install").join("global");
+    let Ok(manifest) = fs::read_to_string(global.join("package.json")) else {
+        return Ok(Vec::new());
+    };
+
+    let manifest: serde_json::Value = serde_json::from_str(&manifest)?;
+    Ok(manifest["dependencies"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, _)| {
+            let package =
+                fs::read_to_string(global.join("node_modules").join(name).join("package.json"))
+                    .ok()?;
+            let package: serde_json::Value = serde_json::from_str(&package).ok()?;
+            Some(InstalledPackage {
+                name: name.clone(),
+                version: package["version"].as_str()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+#[async_trait]
+impl PackageBackend for NodeManager {
+    fn name(&self) -> &'static str {
+        "node"
+    }
+
+    fn display_name(&self) -> &'static str {
+        "Node packages"
+    }
+
+    fn source(&self) -> &'static str {
+        self.package_manager.command()
+    }
+
+    async fn search(&self, query: &str, _limit: usize) -> Result<Vec<PackageInfo>> {
+        Ok(self.info(query).await?.into_iter().collect())
+    }
+
+    async fn display_search_results(&self, query: &str, _results: &[PackageInfo]) -> Result<()> {
+        if let Some(info) = super::get_package_info(query).await? {
+            super::display_package_info(&info);
+        }
+        Ok(())
+    }
+
+    async fn info(&self, name: &str) -> Result<Option<PackageInfo>> {
+        Ok(super::get_package_info(name)
+            .await?
+            .map(|info| PackageInfo {
+                version: info.latest_version().unwrap_or_default(),
+                name: info.name,
+                description: info.description,
+            }))
+    }
+
+    async fn versions(&self, name: &str) -> Result<Vec<String>> {
+        Ok(super::get_package_info(name)
+            .await?
+            .map(|info| info.versions.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn install(&self, name: &str, version: Option<&str>) -> Result<()> {
+        self.install_package(name, version, true).await
+    }
+
+    async fn install_quietly(&self, name: &str, version: Option<&str>) -> Result<()> {
+        self.install_package(name, version, false).await
+    }
+
+    fn supports_parallel_install(&self) -> bool {
+        // yarn, pnpm and bun keep global packages in a single package.json
+        matches!(self.package_manager, NodePackageManager::Npm)
+    }
+
+    async fn uninstall(&self, name: &str) -> Result<()> {
+        self.uninstall_package(name).await
+    }
+
+    async fn upgrade(&self, packages: &[String]) -> Result<()> {
+        self.update_packages(packages).await
+    }
+
+    async fn list(&self) -> Result<Vec<InstalledPackage>> {
+        self.list_packages().await
+    }
+}
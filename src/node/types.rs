@@ -1,3 +1,4 @@
+use crate::backend::InstalledPackage;
 use anyhow::Result;
 use serde::Deserialize;
 
@@ -10,7 +11,7 @@ pub enum NodePackageManager {
 }
 
 impl NodePackageManager {
-    pub fn command(&self) -> &str {
+    pub fn command(&self) -> &'static str {
         match self {
             NodePackageManager::Npm => "npm",
             NodePackageManager::Yarn => "yarn",
@@ -37,12 +38,14 @@ impl NodePackageManager {
         }
     }
 
-    pub fn list_args(&self) -> Vec<&str> {
+    /// Arguments that print the global packages as JSON. Bun can't, its globals are
+    /// read from its global package.json instead.
+    pub fn list_args(&self) -> Option<Vec<&str>> {
         match self {
-            NodePackageManager::Npm => vec!["list", "-g", "--depth=0"],
-            NodePackageManager::Yarn => vec!["global", "list"],
-            NodePackageManager::Pnpm => vec!["list", "-g"],
-            NodePackageManager::Bun => vec!["list", "-g"],
+            NodePackageManager::Npm => Some(vec!["list", "-g", "--depth=0", "--json"]),
+            NodePackageManager::Yarn => Some(vec!["global", "list", "--json"]),
+            NodePackageManager::Pnpm => Some(vec!["list", "-g", "--depth=0", "--json"]),
+            NodePackageManager::Bun => None,
         }
     }
 
@@ -55,6 +58,46 @@ impl NodePackageManager {
         }
     }
 
+    /// Parses the JSON printed for `list_args`. Output that isn't understood yields no
+    /// packages rather than an error.
+    pub fn parse_list_output(&self, output: &str) -> Vec<InstalledPackage> {
+        match self {
+            NodePackageManager::Npm | NodePackageManager::Bun => {
+                // {"dependencies": {"typescript": {"version": "5.3.3"}}}
+                serde_json::from_str::<serde_json::Value>(output)
+                    .map(|root| packages_in(&root))
+                    .unwrap_or_default()
+            }
+            NodePackageManager::Pnpm => {
+                // [{"path": "...", "dependencies": {"typescript": {"version": "5.3.3"}}}]
+                serde_json::from_str::<Vec<serde_json::Value>>(output)
+                    .map(|roots| roots.iter().flat_map(packages_in).collect())
+                    .unwrap_or_default()
+            }
+            NodePackageManager::Yarn => {
+                // One event per line: {"type":"info","data":"\"typescript@5.3.3\" has binaries:"}
+                output
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+                    .filter(|event| event["type"] == "info")
+                    .filter_map(|event| {
+                        let spec = event["data"]
+                            .as_str()?
+                            .strip_prefix('"')?
+                            .split('"')
+                            .next()?;
+                        // Skip the leading '@' of scoped packages when looking for the version
+                        let idx = spec.rfind('@').filter(|&idx| idx > 0)?;
+                        Some(InstalledPackage {
+                            name: spec[..idx].to_string(),
+                            version: spec[idx + 1..].to_string(),
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
     pub fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "npm" => Ok(NodePackageManager::Npm),
@@ -69,6 +112,21 @@ impl NodePackageManager {
     }
 }
 
+/// Packages of a `dependencies` map whose entries carry a `version`.
+fn packages_in(root: &serde_json::Value) -> Vec<InstalledPackage> {
+    root["dependencies"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, package)| {
+            Some(InstalledPackage {
+                name: name.clone(),
+                version: package["version"].as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug, Deserialize)]
 pub struct NpmPackageInfo {
     pub name: String,
@@ -77,11 +135,23 @@ pub struct NpmPackageInfo {
     pub homepage: Option<String>,
     pub license: Option<String>,
     pub author: Option<NpmAuthor>,
-    // pub repository: Option<NpmRepository>,
     pub keywords: Option<Vec<String>>,
     // pub dependencies: Option<serde_json::Value>,
     #[serde(rename = "dist-tags")]
     pub dist_tags: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Every published version, keyed by version number
+    #[serde(default)]
+    pub versions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl NpmPackageInfo {
+    pub fn latest_version(&self) -> Option<String> {
+        self.dist_tags
+            .as_ref()?
+            .get("latest")?
+            .as_str()
+            .map(String::from)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -90,9 +160,66 @@ pub struct NpmAuthor {
     pub email: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct NpmRepository {
-    // #[serde(rename = "type")]
-    // pub repo_type: Option<String>,
-    // pub url: Option<String>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(packages: Vec<InstalledPackage>) -> Vec<String> {
+        packages
+            .into_iter()
+            .map(|package| format!("{}@{}", package.name, package.version))
+            .collect()
+    }
+
+    #[test]
+    fn parses_npm_json() {
+        let output = r#"{
+            "name": "lib",
+            "dependencies": {
+                "@angular/cli": { "version": "17.0.0", "overridden": false },
+                "corepack": { "version": "0.28.0" },
+                "broken": { "missing": true }
+            }
+        }"#;
+        assert_eq!(
+            names(NodePackageManager::Npm.parse_list_output(output)),
+            ["@angular/cli@17.0.0", "corepack@0.28.0"]
+        );
+    }
+
+    #[test]
+    fn parses_pnpm_json() {
+        let output = r#"[{
+            "path": "/home/me/.local/share/pnpm/global/5",
+            "dependencies": {
+                "typescript": { "from": "typescript", "version": "5.3.3" }
+            }
+        }]"#;
+        assert_eq!(
+            names(NodePackageManager::Pnpm.parse_list_output(output)),
+            ["typescript@5.3.3"]
+        );
+    }
+
+    #[test]
+    fn parses_yarn_json_events() {
+        let output = concat!(
+            r#"{"type":"info","data":"\"@vue/cli@5.0.8\" has binaries:"}"#,
+            "\n",
+            r#"{"type":"list","data":{"type":"bin","items":["vue"]}}"#,
+            "\n",
+            r#"{"type":"info","data":"\"typescript@5.3.3\" has binaries:"}"#,
+        );
+        assert_eq!(
+            names(NodePackageManager::Yarn.parse_list_output(output)),
+            ["@vue/cli@5.0.8", "typescript@5.3.3"]
+        );
+    }
+
+    #[test]
+    fn ignores_unparseable_output() {
+        assert!(NodePackageManager::Npm
+            .parse_list_output("+-- typescript@5.3.3")
+            .is_empty());
+    }
 }
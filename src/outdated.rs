@@ -0,0 +1,68 @@
+use crate::backend::{PackageBackend, Registry};
+use crate::version::{self, Severity};
+use anyhow::Result;
+use colored::*;
+
+pub async fn show_outdated(is_cask: bool, is_node: bool) -> Result<()> {
+    let registry = Registry::load()?;
+
+    // Without any flags check every backend
+    let backends: Vec<&dyn PackageBackend> = if is_cask || is_node {
+        vec![registry.select(is_cask, is_node)?]
+    } else {
+        registry.all().collect()
+    };
+
+    println!("Checking for outdated packages 🐕");
+
+    let mut rows = Vec::new();
+    for backend in backends {
+        match backend.outdated().await {
+            Ok(packages) => rows.extend(
+                packages
+                    .into_iter()
+                    .map(|package| (backend.name(), package)),
+            ),
+            Err(e) => println!(
+                "{}",
+                format!(
+                    "Could not check {}: {}",
+                    backend.display_name().to_lowercase(),
+                    e
+                )
+                .yellow()
+            ),
+        }
+    }
+
+    if rows.is_empty() {
+        println!("{}", "Everything is up to date!".green());
+        return Ok(());
+    }
+
+    println!(
+        "\n  {:<30} {:<8} {:<15}   {:<15} Severity",
+        "Package", "Type", "Current", "Latest"
+    );
+    for (kind, package) in &rows {
+        let severity = version::severity(&package.current, &package.latest);
+        let severity = match severity {
+            Severity::Major => severity.to_string().red(),
+            Severity::Minor => severity.to_string().yellow(),
+            Severity::Patch => severity.to_string().green(),
+            Severity::Other => severity.to_string().normal(),
+        };
+
+        println!(
+            "  {:<30} {:<8} {:<15} → {:<15} {}",
+            package.name, kind, package.current, package.latest, severity
+        );
+    }
+
+    println!(
+        "\n{} packages can be updated with {}",
+        rows.len(),
+        "bert update".cyan()
+    );
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::backend::{PackageBackend, PackageInfo};
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub fn is_structured(&self) -> bool {
+        !matches!(self, OutputFormat::Human)
+    }
+}
+
+/// A package as emitted by `--output json|yaml`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageRecord {
+    pub name: String,
+    pub version: String,
+    pub source: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<String>,
+}
+
+impl PackageRecord {
+    pub fn new(backend: &dyn PackageBackend, name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: backend.source().to_string(),
+            kind: backend.name().to_string(),
+            description: None,
+            pinned: None,
+        }
+    }
+
+    pub fn from_info(backend: &dyn PackageBackend, info: &PackageInfo) -> Self {
+        Self {
+            description: info.description.clone(),
+            ..Self::new(backend, &info.name, &info.version)
+        }
+    }
+}
+
+pub fn print_records(format: OutputFormat, records: &[PackageRecord]) -> Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(records)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(records)?),
+        OutputFormat::Human => {
+            for record in records {
+                println!("  {} {} ({})", record.name, record.version, record.kind);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_search_table(query: &str, results: &[PackageInfo]) {
+    println!("\nFound {} matches for {}:", results.len(), query.cyan());
+
+    for info in results {
+        let desc = info.description.as_deref().unwrap_or("");
+        let desc = if desc.chars().count() > 60 {
+            format!("{}...", desc.chars().take(57).collect::<String>())
+        } else {
+            desc.to_string()
+        };
+
+        println!("  {:<30} {:<15} {}", info.name.green(), info.version, desc);
+    }
+}
+
+/// The last non-empty line a failed command wrote to stderr, without brew's `Error: `
+/// prefix, to report a quiet command's failure in one line.
+pub fn error_line(stderr: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.trim_start_matches("Error: ").to_string())
+}
@@ -1,182 +1,542 @@
-use anyhow::{Ok, Result};
+use anyhow::Result;
 use colored::*;
-use std::process::Command;
 
-use crate::{homebrew, node::NodeManager};
+use crate::backend::{PackageBackend, Registry};
+use crate::config::Config;
+use crate::dry_run;
+use crate::history::{self, Action};
+use crate::output::{self, OutputFormat, PackageRecord};
+use crate::spec::PackageSpec;
+use crate::version::VersionReq;
 
-pub async fn search_package(name: &str, is_cask: bool, is_node: bool) -> Result<()> {
-    if is_node {
-        if let Some(npm_info) = crate::node::get_package_info(name).await? {
-            crate::node::display_package_info(&npm_info);
-            return Ok(());
-        }
+pub async fn search_package(
+    name: &str,
+    limit: usize,
+    is_cask: bool,
+    is_node: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+
+    let results = backend.search(name, limit).await?;
+    if format.is_structured() {
+        let records = results
+            .iter()
+            .map(|info| PackageRecord::from_info(backend, info))
+            .collect::<Vec<_>>();
+        return output::print_records(format, &records);
     }
 
-    if let Some(formula) = homebrew::search_formula(
-        &name,
-        if is_cask {
-            Some(homebrew::HomebrewPackageType::Cask)
-        } else {
-            Some(homebrew::HomebrewPackageType::Formula)
-        },
-    )
-    .await?
-    {
-        homebrew::display_package_info(&formula, is_cask);
-        Ok(())
-    } else {
+    if results.is_empty() {
         anyhow::bail!("No packages found matching: {}", name.red());
     }
+
+    backend.display_search_results(name, &results).await
 }
 
 pub async fn uninstall_package(name: &str, is_cask: bool, is_node: bool) -> Result<()> {
-    if is_node {
-        let config = crate::config::Config::load()?;
-        let node_manager = NodeManager::new(config.get_node_package_manager()?);
-        return node_manager.uninstall_package(name).await;
-    }
-
-    return crate::homebrew::uninstall_formula(name, is_cask).await;
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+    history::track(Action::Uninstall, &[backend], backend.uninstall(name)).await
 }
 
 pub async fn install_package(package: &str, is_cask: bool, is_node: bool) -> Result<()> {
     println!("Searching for package {} 🐕", package.cyan());
 
-    if is_node {
-        let config = crate::config::Config::load()?;
-        let node_manager = NodeManager::new(config.get_node_package_manager()?);
-        return node_manager.install_package(package, None).await;
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+
+    let info = backend
+        .info(package)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Package {} not found", package))?;
+
+    println!("Found package: {}", info.name.green());
+    if let Some(desc) = info.description {
+        println!("Description: {}", desc);
     }
+    println!("Version: {}", info.version);
 
-    if let Some(formula) = crate::homebrew::search_formula(
-        package,
-        if is_cask {
-            Some(crate::homebrew::HomebrewPackageType::Cask)
-        } else {
-            Some(crate::homebrew::HomebrewPackageType::Formula)
-        },
-    )
-    .await?
-    {
-        println!("Found package: {}", formula.name.green());
-        if let Some(desc) = formula.desc {
-            println!("Description: {}", desc);
+    history::track(Action::Install, &[backend], backend.install(package, None)).await
+}
+
+/// A package requested on the command line and the backend it should come from.
+#[derive(Debug, Clone)]
+pub struct PackageRequest {
+    pub name: String,
+    pub version: Option<String>,
+    pub backend: &'static str,
+}
+
+impl PackageRequest {
+    fn display_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}@{}", self.name, version),
+            None => self.name.clone(),
         }
-        println!("Version: {}", formula.versions.stable);
+    }
+}
 
-        crate::homebrew::install_formula(&formula.full_name, is_cask).await?;
-        // println!("Successfully installed {}", package.green());
-    } else {
-        println!("Package {} not found in Homebrew", package.red());
+enum InstallOutcome {
+    Installed,
+    NotFound,
+    Failed(String),
+}
+
+pub async fn install_packages(requests: &[PackageRequest], format: OutputFormat) -> Result<()> {
+    let registry = Registry::load()?;
+    let (requests, outcomes) = resolve_ranges(&registry, requests, format).await;
+    let requests = &requests;
+
+    if format.is_structured() {
+        if !dry_run::is_enabled() {
+            anyhow::bail!(
+                "--output {:?} is only supported together with --dry-run",
+                format
+            );
+        }
+        if let Some(InstallOutcome::Failed(reason)) = outcomes.into_iter().flatten().next() {
+            anyhow::bail!(reason);
+        }
+        return print_install_plan(&registry, requests, format).await;
     }
 
-    Ok(())
+    let mut backends: Vec<&dyn PackageBackend> = Vec::new();
+    for request in requests {
+        let backend = registry.get(request.backend)?;
+        if !backends.iter().any(|b| b.name() == backend.name()) {
+            backends.push(backend);
+        }
+    }
+
+    history::track(
+        Action::Install,
+        &backends,
+        install_requests(&registry, requests, outcomes),
+    )
+    .await
 }
 
-pub async fn install_package_version(
-    name: &str,
-    version: Option<&str>,
-    is_cask: bool,
-    is_node: bool,
+/// Installs `requests`, skipping those that already have an outcome (e.g. a range that
+/// matched no version) and reporting every failure in a summary.
+async fn install_requests(
+    registry: &Registry,
+    requests: &[PackageRequest],
+    mut outcomes: Vec<Option<InstallOutcome>>,
 ) -> Result<()> {
-    println!("Searching for package {} 🐕", name.cyan());
+    if let [request] = requests {
+        if let Some(Some(InstallOutcome::Failed(reason))) = outcomes.pop() {
+            anyhow::bail!(reason);
+        }
+        println!("Installing package: {} 🐕", request.name.cyan());
+        if let Some(version) = &request.version {
+            println!("Version: {}", version.cyan());
+        }
+        println!("Searching for package {} 🐕", request.name.cyan());
 
-    if is_node {
-        let config = crate::config::Config::load()?;
-        let node_manager = NodeManager::new(config.get_node_package_manager()?);
-        return node_manager.install_package(name, version).await;
+        return registry
+            .get(request.backend)?
+            .install(&request.name, request.version.as_deref())
+            .await;
     }
 
-    if let Some(formula) = crate::homebrew::search_formula(
-        name,
-        if is_cask {
-            Some(crate::homebrew::HomebrewPackageType::Cask)
-        } else {
-            Some(crate::homebrew::HomebrewPackageType::Formula)
-        },
-    )
-    .await?
-    {
-        crate::homebrew::display_package_info(&formula, is_cask);
+    // Resolve everything up front so typos are reported before anything is installed
+    println!("Resolving {} packages 🐕", requests.len());
+    for (request, outcome) in requests.iter().zip(outcomes.iter_mut()) {
+        if let Some(InstallOutcome::Failed(reason)) = outcome {
+            println!("  {:<30} {} {}", request.display_name(), "✗".red(), reason);
+            continue;
+        }
+        if PackageSpec::parse(&request.name).is_direct() {
+            println!("  {:<30} {} direct", request.display_name(), "✓".green());
+            continue;
+        }
 
-        if let Some(_v) = version {
-            if formula.versioned_formulae.is_empty() {
-                println!(
-                    "\n{}",
-                    "Note: This package doesn't have version-specific formulae available.".yellow()
-                );
+        let resolved = match registry.get(request.backend)?.info(&request.name).await {
+            Ok(Some(info)) => {
                 println!(
-                    "Installing latest version ({}) instead 🐕",
-                    formula.versions.stable
+                    "  {:<30} {} {}",
+                    request.display_name(),
+                    "✓".green(),
+                    info.version
                 );
+                None
+            }
+            Ok(None) => {
+                println!("  {:<30} {} not found", request.display_name(), "✗".red());
+                Some(InstallOutcome::NotFound)
             }
+            Err(e) => {
+                println!("  {:<30} {} {}", request.display_name(), "✗".red(), e);
+                Some(InstallOutcome::Failed(e.to_string()))
+            }
+        };
+        *outcome = resolved;
+    }
+
+    // Backends that can't install concurrently (e.g. Homebrew, which holds a global lock)
+    // run one at a time; the rest are spawned together and print a status line each
+    let mut handles = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        if outcomes[index].is_some() {
+            continue;
         }
 
-        crate::homebrew::install_formula_version(name, version, is_cask).await?;
-        // println!("Successfully installed {}", name.green());
-    } else {
-        println!("Package {} not found in Homebrew", name.red());
+        let backend = registry.get(request.backend)?;
+        if backend.supports_parallel_install() {
+            let request = request.clone();
+            handles.push((
+                index,
+                tokio::spawn(async move {
+                    let result = async {
+                        Registry::load()?
+                            .get(request.backend)?
+                            .install_quietly(&request.name, request.version.as_deref())
+                            .await
+                    }
+                    .await;
+                    match &result {
+                        Ok(()) => println!("  {:<30} {}", request.display_name(), "✓".green()),
+                        Err(e) => {
+                            println!("  {:<30} {} {}", request.display_name(), "✗".red(), e)
+                        }
+                    }
+                    result
+                }),
+            ));
+        } else {
+            let result = backend
+                .install(&request.name, request.version.as_deref())
+                .await;
+            outcomes[index] = Some(into_outcome(result));
+        }
+    }
+
+    for (index, handle) in handles {
+        let result = handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+        outcomes[index] = Some(into_outcome(result));
+    }
+
+    println!("\n{}", "Install summary:".cyan());
+    let mut failures = 0;
+    for (request, outcome) in requests.iter().zip(outcomes) {
+        let status = match outcome.unwrap_or(InstallOutcome::NotFound) {
+            InstallOutcome::Installed if dry_run::is_enabled() => {
+                "✓ would install".green().to_string()
+            }
+            InstallOutcome::Installed => "✓ installed".green().to_string(),
+            InstallOutcome::NotFound => {
+                failures += 1;
+                "✗ not found".red().to_string()
+            }
+            InstallOutcome::Failed(reason) => {
+                failures += 1;
+                format!("{} {}", "✗ failed:".red(), reason)
+            }
+        };
+        println!(
+            "  {:<30} {:<10} {}",
+            request.display_name(),
+            request.backend,
+            status
+        );
+    }
+
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} packages failed to install",
+            failures,
+            requests.len()
+        );
     }
 
     Ok(())
 }
 
-pub async fn update_packages(packages: &Vec<String>, is_node: bool) -> Result<()> {
-    if is_node {
-        let config = crate::config::Config::load()?;
-        let node_manager = NodeManager::new(config.get_node_package_manager()?);
-        return node_manager.update_packages(packages).await;
+/// Replaces version ranges (`^20`, `~5.3`, `>=8 <9`) with the highest version that matches.
+/// A range that can't be resolved only fails its own request, which keeps its spec and gets
+/// a failed outcome at the same index.
+async fn resolve_ranges(
+    registry: &Registry,
+    requests: &[PackageRequest],
+    format: OutputFormat,
+) -> (Vec<PackageRequest>, Vec<Option<InstallOutcome>>) {
+    let mut resolved = Vec::with_capacity(requests.len());
+    let mut outcomes = Vec::with_capacity(requests.len());
+    for request in requests {
+        match resolve_range(registry, request, format).await {
+            Ok(request) => {
+                resolved.push(request);
+                outcomes.push(None);
+            }
+            Err(e) => {
+                resolved.push(request.clone());
+                outcomes.push(Some(InstallOutcome::Failed(e.to_string())));
+            }
+        }
+    }
+
+    (resolved, outcomes)
+}
+
+async fn resolve_range(
+    registry: &Registry,
+    request: &PackageRequest,
+    format: OutputFormat,
+) -> Result<PackageRequest> {
+    let Some(range) = request
+        .version
+        .as_deref()
+        .filter(|v| VersionReq::is_range(v))
+    else {
+        return Ok(request.clone());
+    };
+
+    let req =
+        VersionReq::parse(range).map_err(|e| anyhow::anyhow!("{} for {}", e, request.name))?;
+    let versions = registry
+        .get(request.backend)?
+        .versions(&request.name)
+        .await?;
+    let version = req.max_satisfying(&versions).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No version of {} matches {} ({} versions available)",
+            request.name,
+            range,
+            versions.len()
+        )
+    })?;
+
+    if !format.is_structured() {
+        let matching = versions.iter().filter(|v| req.matches(v)).count();
+        println!(
+            "Resolved {} to {} (highest of {} versions matching {})",
+            request.display_name().cyan(),
+            version.green(),
+            matching,
+            req
+        );
+    }
+
+    Ok(PackageRequest {
+        version: Some(version.to_string()),
+        ..request.clone()
+    })
+}
+
+/// Resolves every request and prints the plan as structured records, without installing.
+async fn print_install_plan(
+    registry: &Registry,
+    requests: &[PackageRequest],
+    format: OutputFormat,
+) -> Result<()> {
+    let mut records = Vec::with_capacity(requests.len());
+    for request in requests {
+        let backend = registry.get(request.backend)?;
+        let info = backend
+            .info(&request.name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Package {} not found", request.name))?;
+
+        records.push(PackageRecord {
+            version: request.version.clone().unwrap_or(info.version.clone()),
+            ..PackageRecord::from_info(backend, &info)
+        });
+    }
+
+    output::print_records(format, &records)
+}
+
+fn into_outcome(result: Result<()>) -> InstallOutcome {
+    match result {
+        Ok(()) => InstallOutcome::Installed,
+        Err(e) => InstallOutcome::Failed(e.to_string()),
     }
+}
+
+pub async fn update_packages(packages: &[String], is_cask: bool, is_node: bool) -> Result<()> {
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+    let config = Config::load()?;
+    let is_pinned = |name: &str| config.pinned_version(backend.name(), name).is_some();
 
-    if packages.is_empty() {
-        println!("{}", "Updating Homebrew 🐕".cyan());
-        let status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .arg("update")
-            .status()?;
+    let has_pins = config.pins.iter().any(|pin| pin.backend == backend.name());
+    let candidates = if packages.is_empty() && has_pins {
+        // Upgrading everything but the pins still starts from up to date metadata
+        backend.refresh().await?;
+        backend
+            .list()
+            .await?
+            .into_iter()
+            .map(|package| package.name)
+            .collect()
+    } else {
+        packages.to_vec()
+    };
 
-        if !status.success() {
-            println!("{}", "Failed to update Homebrew".red());
+    let (skipped, to_update): (Vec<String>, Vec<String>) =
+        candidates.into_iter().partition(|name| is_pinned(name));
+
+    if !skipped.is_empty() {
+        println!("{}", "Skipping pinned packages:".yellow());
+        for name in &skipped {
+            println!(
+                "  {} {}",
+                name,
+                config
+                    .pinned_version(backend.name(), name)
+                    .unwrap_or_default()
+            );
+        }
+        if to_update.is_empty() {
+            println!("Nothing to update 🐕");
             return Ok(());
         }
-        println!("{}", "Homebrew updated successfully".green());
     }
 
-    let packages_to_update = if packages.is_empty() {
-        // Get list of all installed packages
-        let output = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["list", "--formula"])
-            .output()?;
+    history::track(Action::Update, &[backend], backend.upgrade(&to_update)).await
+}
 
-        String::from_utf8_lossy(&output.stdout)
-            .lines()
-            .map(String::from)
-            .collect::<Vec<_>>()
-    } else {
-        packages.clone()
+/// Pins a package at `version`, or at its installed version if none is given.
+pub async fn pin_package(spec: &str, is_cask: bool, is_node: bool) -> Result<()> {
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+    let installed = backend.list().await?;
+
+    // Versioned formulae such as `openssl@3` are names of their own, not a name and a version
+    let (name, version) = match installed.iter().any(|package| package.name == spec) {
+        true => (spec.to_string(), None),
+        false => {
+            let spec = PackageSpec::parse(spec);
+            (spec.name, spec.version)
+        }
     };
 
-    for package in packages_to_update {
-        println!("Updating {} 🐕", package.cyan());
-        let status = Command::new(if cfg!(windows) { "brew.exe" } else { "brew" })
-            .args(["upgrade", &package])
-            .status()?;
+    let package = installed
+        .iter()
+        .find(|package| package.name == name)
+        .ok_or_else(|| anyhow::anyhow!("{} is not installed", name))?;
 
-        if status.success() {
-            println!("{} updated successfully", package.green());
-        } else {
-            println!("Failed to update {}", package.red());
+    // A version matches the installed one exactly or as a prefix (`20` matches `20.11.1`)
+    let version = match version {
+        Some(version)
+            if package.version == version
+                || package.version.starts_with(&format!("{}.", version)) =>
+        {
+            version
         }
+        Some(version) => anyhow::bail!(
+            "{} is installed at {}, not {}",
+            name,
+            package.version,
+            version
+        ),
+        None => package.version.clone(),
+    };
+
+    if dry_run::is_enabled() {
+        println!("Would pin {} at {} 🐕", name.cyan(), version.green());
+    } else {
+        Config::load()?.pin(backend.name(), &name, &version)?;
+        println!("Pinned {} at {} 🐕", name.cyan(), version.green());
+    }
+
+    // Keep e.g. a plain `brew upgrade` from moving it as well
+    if let Err(e) = backend.set_pinned(&name, true).await {
+        println!(
+            "{}",
+            format!("Could not pin {} in {}: {}", name, backend.source(), e).yellow()
+        );
+    }
+    Ok(())
+}
+
+pub async fn unpin_package(name: &str, is_cask: bool, is_node: bool) -> Result<()> {
+    let registry = Registry::load()?;
+    let backend = registry.select(is_cask, is_node)?;
+
+    if dry_run::is_enabled() {
+        if Config::load()?
+            .pinned_version(backend.name(), name)
+            .is_none()
+        {
+            anyhow::bail!("{} is not pinned", name);
+        }
+        println!("Would unpin {} 🐕", name.cyan());
+    } else {
+        if !Config::load()?.unpin(backend.name(), name)? {
+            anyhow::bail!("{} is not pinned", name);
+        }
+        println!("Unpinned {} 🐕", name.cyan());
     }
 
+    if let Err(e) = backend.set_pinned(name, false).await {
+        println!(
+            "{}",
+            format!("Could not unpin {} in {}: {}", name, backend.source(), e).yellow()
+        );
+    }
     Ok(())
 }
 
-pub async fn list_packages(is_node: bool) -> Result<()> {
-    if is_node {
-        let config = crate::config::Config::load()?;
-        let node_manager = NodeManager::new(config.get_node_package_manager()?);
-        return node_manager.list_packages().await;
+pub fn list_pins() -> Result<()> {
+    let config = Config::load()?;
+    if config.pins.is_empty() {
+        println!("No pinned packages");
+        return Ok(());
     }
 
-    return crate::homebrew::list_packages();
+    println!("{}", "Pinned packages:".cyan());
+    for pin in &config.pins {
+        println!("  {:<30} {:<10} {}", pin.name, pin.backend, pin.version);
+    }
+    Ok(())
+}
+
+pub async fn list_packages(is_cask: bool, is_node: bool, format: OutputFormat) -> Result<()> {
+    let registry = Registry::load()?;
+
+    // Without any flags list both formulae and casks, as `brew list` does
+    let backends = if is_cask || is_node {
+        vec![registry.select(is_cask, is_node)?]
+    } else {
+        vec![registry.get("formula")?, registry.get("cask")?]
+    };
+
+    let config = Config::load()?;
+    let pinned_version = |backend: &dyn PackageBackend, name: &str| {
+        config
+            .pinned_version(backend.name(), name)
+            .map(String::from)
+    };
+
+    if format.is_structured() {
+        let mut records = Vec::new();
+        for backend in backends {
+            records.extend(backend.list().await?.iter().map(|package| PackageRecord {
+                pinned: pinned_version(backend, &package.name),
+                ..PackageRecord::new(backend, &package.name, &package.version)
+            }));
+        }
+        return output::print_records(format, &records);
+    }
+
+    println!("{}", "Installed packages:".cyan());
+    for backend in backends {
+        println!("{}", format!("{}:", backend.display_name()).cyan());
+        for package in backend.list().await? {
+            match pinned_version(backend, &package.name) {
+                Some(version) => println!(
+                    "  {} {} {}",
+                    package.name,
+                    package.version,
+                    format!("(pinned at {})", version).yellow()
+                ),
+                None => println!("  {} {}", package.name, package.version),
+            }
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// When set, every confirmation prompt is answered with yes.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Asks a yes/no question. `--yes` answers it up front, and without a terminal
+/// to ask on it is declined.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    if assume_yes() {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    Ok(Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(true)
+        .interact()?)
+}
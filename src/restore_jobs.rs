@@ -0,0 +1,272 @@
+use crate::dry_run;
+use anyhow::Result;
+use colored::*;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::{mpsc, Semaphore};
+
+/// One step of a restore, e.g. `brew install jq`.
+pub struct Job {
+    /// Identifies the job in the progress journal, e.g. `formula:jq`
+    pub key: String,
+    pub label: String,
+    pub work: Work,
+}
+
+pub enum Work {
+    /// A command to run, e.g. `brew` with `["install", "jq"]`
+    Command { program: String, args: Vec<String> },
+    /// An install bert carries out itself, e.g. through a backend. It handles
+    /// `--dry-run` on its own and shouldn't print more than a line.
+    Task(Pin<Box<dyn Future<Output = Result<()>> + Send>>),
+}
+
+pub struct Failure {
+    pub key: String,
+    pub label: String,
+    pub reason: String,
+}
+
+/// Keys of the jobs a restore has completed, one per line in a file next to the backup,
+/// so an interrupted restore can pick up where it stopped.
+pub struct Journal {
+    path: PathBuf,
+    completed: HashSet<String>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`. Unless resuming, earlier progress is discarded.
+    pub fn open(path: PathBuf, resume: bool) -> Result<Self> {
+        let completed = match (resume, path.exists()) {
+            (true, true) => fs::read_to_string(&path)?
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+            (true, false) => {
+                println!(
+                    "{}",
+                    "No restore progress found, restoring everything".yellow()
+                );
+                HashSet::new()
+            }
+            (false, exists) => {
+                if exists && !dry_run::is_enabled() {
+                    fs::remove_file(&path)?;
+                }
+                HashSet::new()
+            }
+        };
+
+        Ok(Self { path, completed })
+    }
+
+    pub fn is_done(&self, key: &str) -> bool {
+        self.completed.contains(key)
+    }
+
+    fn record(&self, key: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", key)?;
+        Ok(())
+    }
+
+    /// Removes the journal once everything has been restored.
+    pub fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `jobs` with at most `limit` at a time, recording each success in the journal.
+/// Jobs the journal already has are skipped.
+pub async fn run(jobs: Vec<Job>, limit: usize, journal: &Arc<Journal>) -> Vec<Failure> {
+    run_chains(
+        jobs.into_iter().map(|job| vec![job]).collect(),
+        limit,
+        journal,
+    )
+    .await
+}
+
+/// Like [`run`], but the jobs of each chain run one after another, e.g. installs that
+/// would otherwise wait on each other's locks. Chains run with at most `limit` jobs at a time.
+pub async fn run_chains(
+    chains: Vec<Vec<Job>>,
+    limit: usize,
+    journal: &Arc<Journal>,
+) -> Vec<Failure> {
+    let semaphore = Arc::new(Semaphore::new(limit.max(1)));
+    let (finished, mut results) = mpsc::unbounded_channel();
+
+    for chain in chains {
+        let mut pending = Vec::new();
+        for job in chain {
+            if journal.is_done(&job.key) {
+                println!("  {:<40} {}", job.label, "already restored".dimmed());
+            } else if dry_run::is_enabled() {
+                match job.work {
+                    Work::Command { program, args } => {
+                        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+                        dry_run::print_command(&program, &args);
+                    }
+                    Work::Task(task) => {
+                        if let Err(e) = task.await {
+                            println!("  {:<40} {} {}", job.label, "✗".red(), e);
+                        }
+                    }
+                }
+            } else {
+                pending.push(job);
+            }
+        }
+        if pending.is_empty() {
+            continue;
+        }
+
+        let semaphore = semaphore.clone();
+        let finished = finished.clone();
+        tokio::spawn(async move {
+            for job in pending {
+                let _permit = semaphore.acquire().await;
+                let result = match job.work {
+                    Work::Command { program, args } => run_command(&program, &args).await,
+                    Work::Task(task) => task.await,
+                };
+                let _ = finished.send(((job.key, job.label), result));
+            }
+        });
+    }
+    drop(finished);
+
+    let mut failures = Vec::new();
+    while let Some(((key, label), result)) = results.recv().await {
+        match result.and_then(|()| journal.record(&key)) {
+            Ok(()) => println!("  {:<40} {}", label, "✓".green()),
+            Err(e) => {
+                println!("  {:<40} {} {}", label, "✗".red(), e);
+                failures.push(Failure {
+                    key,
+                    label,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+
+    failures
+}
+
+async fn run_command(program: &str, args: &[String]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        // Concurrent installs shouldn't each try to update Homebrew
+        .env("HOMEBREW_NO_AUTO_UPDATE", "1")
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not run {}: {}", program, e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let reason = crate::output::error_line(&output.stderr);
+    anyhow::bail!("{}", reason.as_deref().unwrap_or("exited with an error"))
+}
+
+/// Splits `jobs` into chains so that jobs needing any of the same things, e.g. formulae
+/// with a dependency in common, end up in the same chain. Order within a chain is kept.
+pub fn chain_by_shared(jobs: Vec<(Job, HashSet<String>)>) -> Vec<Vec<Job>> {
+    let mut chains: Vec<(Vec<Job>, HashSet<String>)> = Vec::new();
+    for (job, needs) in jobs {
+        let (mut shared, separate): (Vec<_>, Vec<_>) = chains
+            .into_iter()
+            .partition(|(_, chain_needs)| !chain_needs.is_disjoint(&needs));
+
+        let mut merged = (Vec::new(), needs);
+        for (jobs, needs) in shared.drain(..) {
+            merged.0.extend(jobs);
+            merged.1.extend(needs);
+        }
+        merged.0.push(job);
+
+        chains = separate;
+        chains.push(merged);
+    }
+    chains.into_iter().map(|(jobs, _)| jobs).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str, needs: &[&str]) -> (Job, HashSet<String>) {
+        let job = Job {
+            key: format!("formula:{}", name),
+            label: name.to_string(),
+            work: Work::Command {
+                program: "brew".to_string(),
+                args: vec!["install".to_string(), name.to_string()],
+            },
+        };
+        let needs = needs.iter().chain([&name]).map(|s| s.to_string()).collect();
+        (job, needs)
+    }
+
+    fn labels(chains: &[Vec<Job>]) -> Vec<Vec<&str>> {
+        let mut labels = chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .map(|job| job.label.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn keeps_unrelated_jobs_apart() {
+        let chains = chain_by_shared(vec![job("jq", &["oniguruma"]), job("tree", &[])]);
+        assert_eq!(labels(&chains), [vec!["jq"], vec!["tree"]]);
+    }
+
+    #[test]
+    fn chains_jobs_with_a_shared_dependency() {
+        let chains = chain_by_shared(vec![
+            job("wget", &["openssl@3", "libidn2"]),
+            job("tree", &[]),
+            job("curl", &["openssl@3"]),
+            job("ffmpeg", &["x264"]),
+        ]);
+        assert_eq!(
+            labels(&chains),
+            [vec!["ffmpeg"], vec!["tree"], vec!["wget", "curl"]]
+        );
+    }
+
+    #[test]
+    fn merges_chains_a_job_connects() {
+        let chains = chain_by_shared(vec![
+            job("a", &["x"]),
+            job("b", &["y"]),
+            job("c", &["x", "y"]),
+            job("y", &[]),
+        ]);
+        assert_eq!(labels(&chains), [vec!["a", "b", "c", "y"]]);
+    }
+}
@@ -1,9 +1,10 @@
+use crate::config::Config;
 use anyhow::Result;
 use colored::*;
 use serde::Deserialize;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const REPO_OWNER: &str = "michaelessiet"; // Change this to your GitHub username
 const REPO_NAME: &str = "bert-cli";
@@ -33,8 +34,10 @@ pub async fn self_update() -> Result<()> {
     // Get latest release from GitHub
     let client = reqwest::Client::new();
     let url = format!(
-        "https://api.github.com/repos/{}/{}/releases/latest",
-        REPO_OWNER, REPO_NAME
+        "{}/repos/{}/{}/releases/latest",
+        Config::load()?.github_api_url(),
+        REPO_OWNER,
+        REPO_NAME
     );
 
     let response = client
@@ -140,7 +143,7 @@ fn get_platform_asset_name() -> String {
     }
 }
 
-fn get_temp_path(current_exe: &PathBuf) -> PathBuf {
+fn get_temp_path(current_exe: &Path) -> PathBuf {
     let file_name = current_exe.file_name().unwrap();
     let temp_dir = env::temp_dir();
 
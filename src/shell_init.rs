@@ -0,0 +1,78 @@
+use anyhow::Result;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Prints a command-not-found hook that offers to install missing commands through
+/// bert and then re-runs them, e.g. `eval "$(bert shell-init zsh)"`.
+pub fn print_hook(shell: Shell) -> Result<()> {
+    // Call bert by its full path so a missing `bert` on PATH can't recurse into the hook
+    let bert = std::env::current_exe()?;
+    let bert = quote(shell, &bert.to_string_lossy());
+
+    let hook = match shell {
+        Shell::Bash => format!(
+            r#"command_not_found_handle() {{
+    if {bert} command-not-found "$1"; then
+        hash -r
+        "$@"
+        return $?
+    fi
+    printf 'bash: %s: command not found\n' "$1" >&2
+    return 127
+}}"#
+        ),
+        Shell::Zsh => format!(
+            r#"command_not_found_handler() {{
+    if {bert} command-not-found "$1"; then
+        rehash
+        "$@"
+        return $?
+    fi
+    printf 'zsh: command not found: %s\n' "$1" >&2
+    return 127
+}}"#
+        ),
+        Shell::Fish => format!(
+            r#"function fish_command_not_found
+    if {bert} command-not-found $argv[1]
+        $argv
+        return $status
+    end
+    __fish_default_command_not_found_handler $argv
+end"#
+        ),
+    };
+
+    println!("{}", hook);
+    Ok(())
+}
+
+/// Single-quotes `value` for `shell`. POSIX shells can't escape inside single quotes, so
+/// each `'` closes the string, adds an escaped quote and reopens it. fish's single quotes
+/// take `\'` and `\\` instead.
+fn quote(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_paths_with_single_quotes() {
+        let path = "/Users/o'brien/bin/bert";
+        assert_eq!(quote(Shell::Bash, path), r"'/Users/o'\''brien/bin/bert'");
+        assert_eq!(quote(Shell::Zsh, path), r"'/Users/o'\''brien/bin/bert'");
+        assert_eq!(quote(Shell::Fish, path), r"'/Users/o\'brien/bin/bert'");
+        assert_eq!(quote(Shell::Fish, r"C:\bert"), r"'C:\\bert'");
+    }
+}
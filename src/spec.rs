@@ -0,0 +1,182 @@
+use crate::version::VersionReq;
+use std::fmt;
+
+/// Prefixes of specs that npm installs straight from git, a tarball or the filesystem
+const DIRECT_PREFIXES: [&str; 11] = [
+    "git+",
+    "git:",
+    "github:",
+    "gitlab:",
+    "bitbucket:",
+    "gist:",
+    "file:",
+    "http:",
+    "https:",
+    ".",
+    "/",
+];
+
+/// A package as written on the command line or in a Bertfile.
+///
+/// - `jq`, `node@20`, `typescript@~5.3`
+/// - npm scopes and dist-tags: `@angular/cli`, `@scope/pkg@next`
+/// - tap-qualified Homebrew names: `oven-sh/bun/bun`
+/// - git, tarball and path specs, kept whole: `github:user/repo#main`, `./pkg.tgz`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageSpec {
+    pub name: String,
+    /// Version, range or dist-tag following the name's `@`
+    pub version: Option<String>,
+}
+
+impl PackageSpec {
+    pub fn parse(spec: &str) -> Self {
+        let spec = spec.trim();
+        if is_direct(spec) {
+            return Self {
+                name: spec.to_string(),
+                version: None,
+            };
+        }
+
+        // A leading `@` belongs to the npm scope, the version starts at the next one
+        let version_at = spec
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c == '@')
+            .map(|(index, _)| index);
+
+        match version_at {
+            Some(index) => Self {
+                name: spec[..index].to_string(),
+                version: Some(spec[index + 1..].to_string()).filter(|v| !v.is_empty()),
+            },
+            None => Self {
+                name: spec.to_string(),
+                version: None,
+            },
+        }
+    }
+
+    /// Git, tarball and path specs, which don't refer to a registry package.
+    pub fn is_direct(&self) -> bool {
+        is_direct(&self.name)
+    }
+
+    /// The tap of a tap-qualified Homebrew name (`oven-sh/bun` for `oven-sh/bun/bun`).
+    pub fn tap(&self) -> Option<&str> {
+        if self.name.starts_with('@') || self.name.matches('/').count() != 2 {
+            return None;
+        }
+        self.name.rsplit_once('/').map(|(tap, _)| tap)
+    }
+}
+
+impl fmt::Display for PackageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// Whether a version is a dist-tag like `latest` or `next` rather than a version or range.
+pub fn is_dist_tag(version: &str) -> bool {
+    let version = version
+        .strip_prefix('v')
+        .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(version);
+
+    !VersionReq::is_range(version) && !version.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn is_direct(spec: &str) -> bool {
+    if DIRECT_PREFIXES
+        .iter()
+        .any(|prefix| spec.starts_with(prefix))
+        || spec.contains("://")
+    {
+        return true;
+    }
+    if spec.ends_with(".tgz") || spec.ends_with(".tar.gz") || spec.ends_with(".tar") {
+        return true;
+    }
+
+    // npm's GitHub shorthand `user/repo`, as opposed to scopes and `user/tap/formula`
+    !spec.starts_with('@') && spec.matches('/').count() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `spec` and checks it prints back unchanged, with the expected parts.
+    fn check(spec: &str, name: &str, version: Option<&str>, tap: Option<&str>, direct: bool) {
+        let parsed = PackageSpec::parse(spec);
+        assert_eq!(parsed.name, name, "name of {}", spec);
+        assert_eq!(parsed.version.as_deref(), version, "version of {}", spec);
+        assert_eq!(parsed.tap(), tap, "tap of {}", spec);
+        assert_eq!(parsed.is_direct(), direct, "is_direct of {}", spec);
+        assert_eq!(parsed.to_string(), spec, "round trip of {}", spec);
+    }
+
+    #[test]
+    fn plain_names() {
+        check("jq", "jq", None, None, false);
+        check("node@20", "node", Some("20"), None, false);
+    }
+
+    #[test]
+    fn npm_scopes() {
+        check("@angular/cli", "@angular/cli", None, None, false);
+        check("@scope/pkg@next", "@scope/pkg", Some("next"), None, false);
+    }
+
+    #[test]
+    fn tap_qualified_names() {
+        check(
+            "oven-sh/bun/bun",
+            "oven-sh/bun/bun",
+            None,
+            Some("oven-sh/bun"),
+            false,
+        );
+        check(
+            "oven-sh/bun/bun@1.0",
+            "oven-sh/bun/bun",
+            Some("1.0"),
+            Some("oven-sh/bun"),
+            false,
+        );
+    }
+
+    #[test]
+    fn direct_specs() {
+        check(
+            "github:user/repo#main",
+            "github:user/repo#main",
+            None,
+            None,
+            true,
+        );
+        check("user/repo", "user/repo", None, None, true);
+        check("./pkg.tgz", "./pkg.tgz", None, None, true);
+    }
+
+    #[test]
+    fn dist_tags() {
+        assert!(is_dist_tag("next"));
+        assert!(is_dist_tag("latest"));
+        assert!(!is_dist_tag("20"));
+        assert!(!is_dist_tag("1.0"));
+        assert!(!is_dist_tag("v1.2.3"));
+        assert!(!is_dist_tag("^5.3"));
+        assert!(!is_dist_tag("1.x"));
+
+        let tagged = PackageSpec::parse("@scope/pkg@next");
+        assert!(tagged.version.as_deref().is_some_and(is_dist_tag));
+        let versioned = PackageSpec::parse("node@20");
+        assert!(!versioned.version.as_deref().is_some_and(is_dist_tag));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::Config;
+use crate::homebrew::{self, brew};
+use crate::{dry_run, prompt, version_tap};
+use anyhow::Result;
+use colored::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::{Command, Output};
+
+/// `brew tap-info --json` output for one tap
+#[derive(Debug, Deserialize)]
+struct TapInfo {
+    name: String,
+    #[serde(default)]
+    installed: bool,
+    #[serde(default)]
+    official: bool,
+    remote: Option<String>,
+    last_commit: Option<String>,
+    /// Commit the tap's checkout is at
+    #[serde(rename = "HEAD")]
+    head: Option<String>,
+    #[serde(default)]
+    formula_names: Vec<String>,
+    #[serde(default)]
+    cask_tokens: Vec<String>,
+}
+
+/// Taps maintained by Homebrew itself, and bert's own local tap, are always trusted.
+fn is_builtin(tap: &str) -> bool {
+    tap.starts_with("homebrew/") || tap == version_tap::VERSIONS_TAP
+}
+
+pub fn is_trusted(config: &Config, tap: &str) -> bool {
+    is_builtin(tap) || config.trusted_taps.iter().any(|trusted| trusted == tap)
+}
+
+pub fn list_taps() -> Result<Vec<String>> {
+    let output = Command::new(brew()).arg("tap").output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// Taps `tap` on behalf of an install, e.g. for `oven-sh/bun/bun`. Third-party taps
+/// must be trusted in the config, approved at the prompt, or already tapped.
+pub fn ensure_tap(tap: &str) -> Result<()> {
+    let mut config = Config::load()?;
+    if !is_trusted(&config, tap) && !list_taps()?.iter().any(|installed| installed == tap) {
+        // On stderr along with the prompt, stdout may hold structured output
+        eprintln!(
+            "{} is a third-party tap that isn't in your trusted taps.",
+            tap.yellow()
+        );
+        if !prompt::confirm(&format!("Trust and tap {}?", tap))? {
+            anyhow::bail!(
+                "Refusing to tap untrusted tap {}. Run `bert tap add {}` to trust it",
+                tap,
+                tap
+            );
+        }
+
+        if !dry_run::is_enabled() {
+            config.trusted_taps.push(tap.to_string());
+            config.save()?;
+        }
+    }
+
+    if dry_run::is_enabled() {
+        dry_run::print_command(brew(), &["tap", tap]);
+        return Ok(());
+    }
+
+    let status = Command::new(brew()).args(["tap", tap]).status()?;
+    if !status.success() {
+        anyhow::bail!("Failed to add tap {}", tap);
+    }
+    Ok(())
+}
+
+pub fn add_tap(tap: &str) -> Result<()> {
+    println!("Adding tap {} 🐕", tap.cyan());
+    if !homebrew::run_brew_step(tap, &["tap", tap])? {
+        anyhow::bail!("Failed to add tap {}", tap);
+    }
+
+    let mut config = Config::load()?;
+    if !dry_run::is_enabled() && !is_trusted(&config, tap) {
+        config.trusted_taps.push(tap.to_string());
+        config.save()?;
+    }
+    Ok(())
+}
+
+pub fn remove_tap(tap: &str) -> Result<()> {
+    println!("Removing tap {} 🐕", tap.cyan());
+    if !homebrew::run_brew_step(tap, &["untap", tap])? {
+        anyhow::bail!("Failed to remove tap {}", tap);
+    }
+
+    let mut config = Config::load()?;
+    if !dry_run::is_enabled() && config.trusted_taps.iter().any(|trusted| trusted == tap) {
+        config.trusted_taps.retain(|trusted| trusted != tap);
+        config.save()?;
+    }
+    Ok(())
+}
+
+/// The commit each of `taps` is checked out at. Taps without git history are left out.
+pub fn head_commits(taps: &[String]) -> Result<HashMap<String, String>> {
+    if taps.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let output = Command::new(brew())
+        .args(["tap-info", "--json"])
+        .args(taps)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("Could not get information about taps");
+    }
+
+    Ok(serde_json::from_slice::<Vec<TapInfo>>(&output.stdout)?
+        .into_iter()
+        .filter_map(|info| Some((info.name, info.head?)))
+        .collect())
+}
+
+/// A tap checked out at a locked commit by [`checkout_commit`].
+pub struct Checkout {
+    pub tap: String,
+    repository: String,
+    /// The branch the tap was on, or its commit if it was already detached
+    original: String,
+}
+
+impl Checkout {
+    /// Puts the tap back on the branch or commit it was on before.
+    pub fn restore(self) -> Result<()> {
+        if dry_run::is_enabled() {
+            dry_run::print_command("git", &["-C", &self.repository, "checkout", &self.original]);
+            return Ok(());
+        }
+        if !git(&self.repository, &["checkout", "--quiet", &self.original])?
+            .status
+            .success()
+        {
+            anyhow::bail!("could not switch back to {}", self.original);
+        }
+        Ok(())
+    }
+}
+
+/// Checks `tap` out at `commit`, fetching it first if the clone doesn't have it yet.
+/// Restore the returned checkout once done, so `brew update` can move the tap again.
+pub fn checkout_commit(tap: &str, commit: &str) -> Result<Checkout> {
+    let output = Command::new(brew()).args(["--repository", tap]).output()?;
+    let repository = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || repository.is_empty() {
+        anyhow::bail!("could not locate the {} checkout", tap);
+    }
+
+    let head = |args: &[&str]| -> Result<Option<String>> {
+        let output = git(&repository, args)?;
+        let head = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success() && !head.is_empty()).then_some(head))
+    };
+    let original = match head(&["symbolic-ref", "--quiet", "--short", "HEAD"])? {
+        Some(branch) => branch,
+        None => match head(&["rev-parse", "HEAD"])? {
+            Some(commit) => commit,
+            // A dry run may not have tapped it yet, `-` is whatever was checked out before
+            None if dry_run::is_enabled() => "-".to_string(),
+            None => anyhow::bail!("the {} checkout has no commits", tap),
+        },
+    };
+    let checkout = Checkout {
+        tap: tap.to_string(),
+        repository,
+        original,
+    };
+
+    if dry_run::is_enabled() {
+        dry_run::print_command(
+            "git",
+            &["-C", &checkout.repository, "checkout", "--detach", commit],
+        );
+        return Ok(checkout);
+    }
+
+    let succeeds =
+        |args: &[&str]| -> Result<bool> { Ok(git(&checkout.repository, args)?.status.success()) };
+    let object = format!("{}^{{commit}}", commit);
+    if !succeeds(&["cat-file", "-e", &object])?
+        && !succeeds(&["fetch", "--quiet", "origin", commit])?
+    {
+        anyhow::bail!("commit {} is not available from the tap's remote", commit);
+    }
+    if !succeeds(&["checkout", "--quiet", "--detach", commit])? {
+        anyhow::bail!("could not check out commit {}", commit);
+    }
+    Ok(checkout)
+}
+
+fn git(repository: &str, args: &[&str]) -> Result<Output> {
+    Ok(Command::new("git")
+        .args(["-C", repository])
+        .args(args)
+        .output()?)
+}
+
+pub fn show_taps() -> Result<()> {
+    let config = Config::load()?;
+    let taps = list_taps()?;
+    if taps.is_empty() {
+        println!("No taps installed");
+        return Ok(());
+    }
+
+    println!("{}", "Installed taps:".cyan());
+    for tap in taps {
+        let trust = match (is_builtin(&tap), is_trusted(&config, &tap)) {
+            (true, _) => "built-in".green(),
+            (false, true) => "trusted".green(),
+            (false, false) => "untrusted".yellow(),
+        };
+        println!("  {:<40} {}", tap, trust);
+    }
+    Ok(())
+}
+
+pub fn show_tap_info(tap: &str) -> Result<()> {
+    let output = Command::new(brew())
+        .args(["tap-info", "--json", tap])
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("Could not get information about tap {}", tap);
+    }
+
+    let info = serde_json::from_slice::<Vec<TapInfo>>(&output.stdout)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Tap {} not found", tap))?;
+    let config = Config::load()?;
+
+    println!("\nTap Information:");
+    println!("  Name: {}", info.name.green());
+    println!("  Installed: {}", if info.installed { "yes" } else { "no" });
+    println!(
+        "  Trust: {}",
+        match (info.official, is_trusted(&config, &info.name)) {
+            (true, _) => "official",
+            (false, true) => "trusted",
+            (false, false) => "untrusted",
+        }
+    );
+    if let Some(remote) = &info.remote {
+        println!("  Remote: {}", remote);
+    }
+    println!("  Formulae: {}", info.formula_names.len());
+    println!("  Casks: {}", info.cask_tokens.len());
+    if let Some(last_commit) = &info.last_commit {
+        println!("  Last updated: {}", last_commit);
+    }
+    Ok(())
+}
@@ -0,0 +1,452 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Size of the jump between an installed version and a newer one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Major,
+    Minor,
+    Patch,
+    /// Build revisions (`1.7.1_1`) and versions that don't follow `x.y.z`
+    Other,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Major => "major",
+            Severity::Minor => "minor",
+            Severity::Patch => "patch",
+            Severity::Other => "other",
+        };
+        f.pad(name)
+    }
+}
+
+/// Splits a version like `v1.7.1` or Homebrew's `1.7.1_2` into numeric components.
+/// Returns `None` for versions that aren't purely numeric (e.g. `latest` or `2.0.0-rc.1`).
+fn components(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '_'])
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Drops Homebrew's bottle revision (`1.7.1_1` → `1.7.1`), which can't be installed
+/// as a version of its own.
+pub fn without_revision(version: &str) -> &str {
+    match version.rsplit_once('_') {
+        Some((version, revision)) if revision.parse::<u32>().is_ok() => version,
+        _ => version,
+    }
+}
+
+fn compare_components(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Whether `latest` is newer than `current`. Versions that can't be compared
+/// numerically are considered newer whenever they differ.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    match (components(latest), components(current)) {
+        (Some(latest), Some(current)) => compare_components(&latest, &current).is_gt(),
+        _ => latest != current,
+    }
+}
+
+pub fn severity(current: &str, latest: &str) -> Severity {
+    let (Some(current), Some(latest)) = (components(current), components(latest)) else {
+        return Severity::Other;
+    };
+
+    let first_difference = (0..current.len().max(latest.len()))
+        .find(|&i| current.get(i).unwrap_or(&0) != latest.get(i).unwrap_or(&0));
+
+    match first_difference {
+        Some(0) => Severity::Major,
+        Some(1) => Severity::Minor,
+        Some(2) => Severity::Patch,
+        _ => Severity::Other,
+    }
+}
+
+/// A comparison against one version, e.g. the `>=8` in `>=8 <9`.
+#[derive(Debug, Clone)]
+struct Comparator {
+    op: Ordering,
+    inclusive: bool,
+    version: Vec<u64>,
+}
+
+impl Comparator {
+    fn matches(&self, version: &[u64]) -> bool {
+        let ordering = compare_components(version, &self.version);
+        ordering == self.op || (self.inclusive && ordering.is_eq())
+    }
+}
+
+impl fmt::Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match (self.op, self.inclusive) {
+            (Ordering::Greater, true) => ">=",
+            (Ordering::Greater, false) => ">",
+            (Ordering::Less, true) => "<=",
+            (Ordering::Less, false) => "<",
+            (Ordering::Equal, _) => "=",
+        };
+        let version = self
+            .version
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        write!(f, "{}{}", op, version)
+    }
+}
+
+/// An npm-style version range such as `^20`, `~5.3`, `>=8 <9` or `1.2 - 1.4 || 2`.
+#[derive(Debug, Clone)]
+pub struct VersionReq {
+    /// Alternatives separated by `||`, each a set of comparators that must all match
+    alternatives: Vec<Vec<Comparator>>,
+}
+
+impl VersionReq {
+    /// Whether `spec` is a range rather than a plain version or tag like `20` or `latest`.
+    pub fn is_range(spec: &str) -> bool {
+        spec.contains(['^', '~', '<', '>', '=', '*', '|', ' '])
+            || spec.split('.').any(|part| part == "x" || part == "X")
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let alternatives = spec
+            .split("||")
+            .map(parse_comparator_set)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { alternatives })
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        let Some(version) = components(version) else {
+            return false;
+        };
+        self.alternatives
+            .iter()
+            .any(|set| set.iter().all(|comparator| comparator.matches(&version)))
+    }
+
+    /// The highest of `versions` that satisfies the range.
+    pub fn max_satisfying<'a>(&self, versions: &'a [String]) -> Option<&'a str> {
+        versions
+            .iter()
+            .filter(|version| self.matches(version))
+            .max_by(|a, b| {
+                compare_components(
+                    &components(a).unwrap_or_default(),
+                    &components(b).unwrap_or_default(),
+                )
+            })
+            .map(String::as_str)
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let alternatives = self
+            .alternatives
+            .iter()
+            .map(|set| match set.is_empty() {
+                true => "any version".to_string(),
+                false => set
+                    .iter()
+                    .map(Comparator::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+            .collect::<Vec<_>>();
+        f.write_str(&alternatives.join(" || "))
+    }
+}
+
+fn parse_comparator_set(spec: &str) -> Result<Vec<Comparator>, String> {
+    let mut tokens = Vec::<String>::new();
+    for token in spec.split_whitespace() {
+        // Allow a space between an operator and its version (`>= 8`)
+        match tokens.last_mut() {
+            Some(last) if matches!(last.as_str(), "^" | "~" | "<" | ">" | "<=" | ">=" | "=") => {
+                last.push_str(token)
+            }
+            _ => tokens.push(token.to_string()),
+        }
+    }
+
+    let mut comparators = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        // Hyphen range: `1.2 - 1.4`
+        if tokens.get(index + 1).is_some_and(|token| token == "-") {
+            let upper = tokens
+                .get(index + 2)
+                .ok_or_else(|| format!("Incomplete range: {}", spec.trim()))?;
+            let (lower, _) = parse_partial(&tokens[index])?;
+            let (upper, precision) = parse_partial(upper)?;
+            comparators.push(at_least(lower));
+            // A wildcard upper bound (`1.2.3 - *`) leaves the range open-ended
+            match precision {
+                0 => {}
+                3 => comparators.push(at_most(upper)),
+                _ => comparators.push(below(bump(&upper, precision)?)),
+            }
+            index += 3;
+            continue;
+        }
+
+        comparators.extend(parse_comparator(&tokens[index])?);
+        index += 1;
+    }
+
+    Ok(comparators)
+}
+
+fn parse_comparator(token: &str) -> Result<Vec<Comparator>, String> {
+    let (op, rest) = ["<=", ">=", "<", ">", "=", "^", "~"]
+        .iter()
+        .find_map(|op| token.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", token));
+    let (version, precision) = parse_partial(rest)?;
+
+    if precision == 0 {
+        // `*`, `x` or an empty range matches anything except `<*` and `>*`
+        return match op {
+            "<" | ">" => Err(format!("Invalid range: {}", token)),
+            _ => Ok(Vec::new()),
+        };
+    }
+
+    let comparators = match op {
+        "^" => {
+            // Bump the first non-zero component, or the last given one if all are zero
+            let significant = version
+                .iter()
+                .take(precision)
+                .position(|&part| part != 0)
+                .map_or(precision, |i| i + 1);
+            vec![
+                at_least(version.clone()),
+                below(bump(&version, significant)?),
+            ]
+        }
+        "~" => vec![
+            at_least(version.clone()),
+            below(bump(&version, precision.min(2))?),
+        ],
+        ">=" => vec![at_least(version)],
+        "<" => vec![below(version)],
+        ">" if precision < 3 => vec![at_least(bump(&version, precision)?)],
+        ">" => vec![Comparator {
+            op: Ordering::Greater,
+            inclusive: false,
+            version,
+        }],
+        "<=" if precision < 3 => vec![below(bump(&version, precision)?)],
+        "<=" => vec![at_most(version)],
+        _ if precision < 3 => vec![at_least(version.clone()), below(bump(&version, precision)?)],
+        _ => vec![Comparator {
+            op: Ordering::Equal,
+            inclusive: true,
+            version,
+        }],
+    };
+
+    Ok(comparators)
+}
+
+/// Parses `1`, `1.2`, `1.2.x` or `1.2.3` into three components and how many were given.
+fn parse_partial(version: &str) -> Result<(Vec<u64>, usize), String> {
+    let mut parts = Vec::new();
+    for part in version.trim_start_matches(['v', '=']).split('.') {
+        if matches!(part, "" | "x" | "X" | "*") {
+            break;
+        }
+        parts.push(
+            part.parse()
+                .map_err(|_| format!("Invalid version in range: {}", version))?,
+        );
+    }
+
+    if parts.len() > 3 {
+        return Err(format!("Invalid version in range: {}", version));
+    }
+
+    let precision = parts.len();
+    parts.resize(3, 0);
+    Ok((parts, precision))
+}
+
+/// Increments the component at `precision - 1` and zeroes everything after it.
+fn bump(version: &[u64], precision: usize) -> Result<Vec<u64>, String> {
+    if precision == 0 || precision > version.len() {
+        return Err(format!(
+            "Cannot bump component {} of a {}-part version",
+            precision,
+            version.len()
+        ));
+    }
+
+    let mut bumped = version.to_vec();
+    bumped[precision - 1] += 1;
+    bumped[precision..].iter_mut().for_each(|part| *part = 0);
+    Ok(bumped)
+}
+
+fn at_least(version: Vec<u64>) -> Comparator {
+    Comparator {
+        op: Ordering::Greater,
+        inclusive: true,
+        version,
+    }
+}
+
+fn at_most(version: Vec<u64>) -> Comparator {
+    Comparator {
+        op: Ordering::Less,
+        inclusive: true,
+        version,
+    }
+}
+
+fn below(version: Vec<u64>) -> Comparator {
+    Comparator {
+        op: Ordering::Less,
+        inclusive: false,
+        version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(list: &[&str]) -> Vec<String> {
+        list.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn satisfies(range: &str, version: &str) -> bool {
+        VersionReq::parse(range).unwrap().matches(version)
+    }
+
+    #[test]
+    fn caret_ranges() {
+        assert!(satisfies("^1.2.3", "1.2.3"));
+        assert!(satisfies("^1.2.3", "1.9.0"));
+        assert!(!satisfies("^1.2.3", "2.0.0"));
+        assert!(!satisfies("^1.2.3", "1.2.2"));
+        assert!(satisfies("^0.2.3", "0.2.9"));
+        assert!(!satisfies("^0.2.3", "0.3.0"));
+        assert!(satisfies("^0.0.3", "0.0.3"));
+        assert!(!satisfies("^0.0.3", "0.0.4"));
+        assert!(satisfies("^20", "20.11.1"));
+        assert!(!satisfies("^20", "21.0.0"));
+    }
+
+    #[test]
+    fn tilde_ranges() {
+        assert!(satisfies("~1.2.3", "1.2.9"));
+        assert!(!satisfies("~1.2.3", "1.3.0"));
+        assert!(satisfies("~5.3", "5.3.3"));
+        assert!(!satisfies("~5.3", "5.4.0"));
+        assert!(satisfies("~1", "1.9.9"));
+        assert!(!satisfies("~1", "2.0.0"));
+    }
+
+    #[test]
+    fn comparator_ranges() {
+        assert!(satisfies(">=8 <9", "8.5.0"));
+        assert!(!satisfies(">=8 <9", "9.0.0"));
+        assert!(!satisfies(">=8 <9", "7.9.9"));
+        assert!(satisfies(">= 8", "10.0.0"));
+        assert!(satisfies(">1.2", "1.3.0"));
+        assert!(!satisfies(">1.2", "1.2.9"));
+        assert!(satisfies("<=1.2", "1.2.9"));
+        assert!(!satisfies("<=1.2", "1.3.0"));
+        assert!(satisfies(">1.2.3", "1.2.4"));
+        assert!(!satisfies(">1.2.3", "1.2.3"));
+    }
+
+    #[test]
+    fn x_ranges() {
+        assert!(satisfies("1.x", "1.5.0"));
+        assert!(!satisfies("1.x", "2.0.0"));
+        assert!(satisfies("1.2.X", "1.2.7"));
+        assert!(!satisfies("1.2.x", "1.3.0"));
+        assert!(satisfies("*", "0.0.1"));
+        assert!(satisfies("x", "99.0.0"));
+        assert!(VersionReq::parse("<*").is_err());
+    }
+
+    #[test]
+    fn hyphen_ranges() {
+        assert!(satisfies("1.2.3 - 2.3.4", "2.3.4"));
+        assert!(!satisfies("1.2.3 - 2.3.4", "2.3.5"));
+        assert!(!satisfies("1.2.3 - 2.3.4", "1.2.2"));
+        assert!(satisfies("1.2 - 2.3", "2.3.9"));
+        assert!(!satisfies("1.2 - 2.3", "2.4.0"));
+        assert!(satisfies("1.2.3 - *", "99.0.0"));
+        assert!(!satisfies("1.2.3 - *", "1.2.2"));
+        assert!(satisfies("1 - x", "1.0.0"));
+        assert!(satisfies("1 - x", "42.1.0"));
+        assert!(VersionReq::parse("1.2.3 -").is_err());
+    }
+
+    #[test]
+    fn or_ranges() {
+        assert!(satisfies("^1 || ^3", "1.4.0"));
+        assert!(satisfies("^1 || ^3", "3.0.1"));
+        assert!(!satisfies("^1 || ^3", "2.0.0"));
+        assert!(satisfies("1.2 - 1.4 || >=2.1", "2.5.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        assert!(VersionReq::parse("^1.a").is_err());
+        assert!(VersionReq::parse("1.2.3.4").is_err());
+    }
+
+    #[test]
+    fn bump_rejects_zero_precision() {
+        assert!(bump(&[1, 2, 3], 0).is_err());
+        assert_eq!(bump(&[1, 2, 3], 2).unwrap(), vec![1, 3, 0]);
+    }
+
+    #[test]
+    fn max_satisfying_picks_highest_match() {
+        let available = versions(&["1.0.0", "1.2.3", "1.9.0", "2.0.0", "2.1.0", "3.0.0"]);
+        let max = |range: &str| {
+            VersionReq::parse(range)
+                .unwrap()
+                .max_satisfying(&available)
+                .map(String::from)
+        };
+
+        assert_eq!(max("^1.2").as_deref(), Some("1.9.0"));
+        assert_eq!(max("~1.2").as_deref(), Some("1.2.3"));
+        assert_eq!(max(">=2 <3").as_deref(), Some("2.1.0"));
+        assert_eq!(max("2.x").as_deref(), Some("2.1.0"));
+        assert_eq!(max("1.0.0 - 2.0.0").as_deref(), Some("2.0.0"));
+        assert_eq!(max("1.2.3 - *").as_deref(), Some("3.0.0"));
+        assert_eq!(max("^1 || ^2").as_deref(), Some("2.1.0"));
+        assert_eq!(max("^4"), None);
+    }
+
+    #[test]
+    fn strips_revisions() {
+        assert_eq!(without_revision("1.7.1_1"), "1.7.1");
+        assert_eq!(without_revision("1.7.1"), "1.7.1");
+        assert_eq!(without_revision("2024_beta"), "2024_beta");
+    }
+}
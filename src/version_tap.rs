@@ -0,0 +1,259 @@
+use crate::config::Config;
+use crate::homebrew::{self, brew};
+use crate::{dry_run, http_cache};
+use anyhow::{Context, Result};
+use colored::*;
+use reqwest::header;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Local tap that holds formulae extracted from homebrew-core history
+pub const VERSIONS_TAP: &str = "bert/versions";
+
+const CORE_REPO: &str = "Homebrew/homebrew-core";
+
+/// How many pages of formula history to scan before giving up
+const MAX_HISTORY_PAGES: u32 = 10;
+
+#[derive(Deserialize)]
+struct CommitEntry {
+    sha: String,
+    commit: CommitDetails,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    message: String,
+}
+
+/// Installs `name` at exactly `version` from the `bert/versions` tap, extracting the
+/// formula from homebrew-core history first if it isn't in the tap yet.
+pub async fn install_version(name: &str, version: &str) -> Result<()> {
+    let versioned_name = format!("{}@{}", name, version);
+    let qualified_name = format!("{}/{}", VERSIONS_TAP, versioned_name);
+
+    println!(
+        "{} {} isn't shipped by Homebrew anymore, using {} 🐕",
+        name.cyan(),
+        version.cyan(),
+        VERSIONS_TAP.cyan()
+    );
+
+    if dry_run::is_enabled() {
+        dry_run::print_command(brew(), &["tap-new", "--no-git", VERSIONS_TAP]);
+        println!(
+            "  {} extract {} {} from {} history",
+            "[dry-run]".yellow(),
+            name,
+            version,
+            CORE_REPO
+        );
+        return homebrew::run_brew_install(&["install", &qualified_name], &qualified_name).await;
+    }
+
+    let tap_dir = ensure_tap()?;
+    let formula_path = tap_dir
+        .join("Formula")
+        .join(format!("{}.rb", versioned_name));
+
+    if !formula_path.exists() {
+        if core_has_history() {
+            extract_locally(name, version)?;
+        } else {
+            let source = fetch_historical_formula(name, version).await?;
+            fs::create_dir_all(tap_dir.join("Formula"))?;
+            fs::write(&formula_path, rewrite_formula(&source, &versioned_name))?;
+        }
+        println!(
+            "  Extracted {} into {}",
+            versioned_name.green(),
+            VERSIONS_TAP
+        );
+    }
+
+    homebrew::run_brew_install(&["install", &qualified_name], &qualified_name).await
+}
+
+fn brew_output(args: &[&str]) -> Option<String> {
+    let output = Command::new(brew()).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Creates the local tap if needed and returns its directory.
+fn ensure_tap() -> Result<PathBuf> {
+    let taps = brew_output(&["tap"]).unwrap_or_default();
+    if !taps.lines().any(|tap| tap == VERSIONS_TAP) {
+        let status = Command::new(brew())
+            .args(["tap-new", "--no-git", VERSIONS_TAP])
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("Failed to create the {} tap", VERSIONS_TAP);
+        }
+    }
+
+    brew_output(&["--repository", VERSIONS_TAP])
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Could not locate the {} tap", VERSIONS_TAP))
+}
+
+/// `brew extract` needs a full git checkout of homebrew-core, which most installs
+/// no longer have since Homebrew switched to the JSON API.
+fn core_has_history() -> bool {
+    brew_output(&["--repository", "homebrew/core"])
+        .map(|path| PathBuf::from(path).join(".git").exists())
+        .unwrap_or(false)
+}
+
+fn extract_locally(name: &str, version: &str) -> Result<()> {
+    let version_arg = format!("--version={}", version);
+    let status = Command::new(brew())
+        .args(["extract", &version_arg, name, VERSIONS_TAP])
+        .status()?;
+
+    if !status.success() {
+        anyhow::bail!("Could not extract {} {} from homebrew/core", name, version);
+    }
+    Ok(())
+}
+
+/// Finds the newest homebrew-core commit that shipped `version` and returns the
+/// formula source at that commit.
+async fn fetch_historical_formula(name: &str, version: &str) -> Result<String> {
+    let api_url = Config::load()?.github_api_url();
+
+    for path in formula_paths(name) {
+        if let Some(sha) = find_version_commit(&api_url, &path, name, version).await? {
+            let url = format!(
+                "{}/repos/{}/contents/{}?ref={}",
+                api_url, CORE_REPO, path, sha
+            );
+            let response = http_cache::client()
+                .get(&url)
+                .header(header::USER_AGENT, "bert")
+                .header(header::ACCEPT, "application/vnd.github.raw")
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                anyhow::bail!("Failed to download {} at {}", path, &sha[..7]);
+            }
+            return Ok(response.text().await?);
+        }
+    }
+
+    anyhow::bail!(
+        "Version {} of {} was not found in the {} history",
+        version,
+        name,
+        CORE_REPO
+    )
+}
+
+/// Formulae were moved into per-letter directories in 2023, older history lives at the root.
+fn formula_paths(name: &str) -> [String; 2] {
+    let shard = if name.starts_with("lib") {
+        "lib".to_string()
+    } else {
+        name.chars().take(1).collect()
+    };
+
+    [
+        format!("Formula/{}/{}.rb", shard, name),
+        format!("Formula/{}.rb", name),
+    ]
+}
+
+async fn find_version_commit(
+    api_url: &str,
+    path: &str,
+    name: &str,
+    version: &str,
+) -> Result<Option<String>> {
+    // Version bumps are committed as "name 1.2.3", followed by "name: update 1.2.3 bottle."
+    let bump = format!("{} {}", name, version);
+    let bottle = format!("{}: update {} bottle", name, version);
+
+    for page in 1..=MAX_HISTORY_PAGES {
+        let url = format!(
+            "{}/repos/{}/commits?path={}&per_page=100&page={}",
+            api_url, CORE_REPO, path, page
+        );
+        let Some(body) = http_cache::get(&url).await? else {
+            return Ok(None);
+        };
+
+        let commits: Vec<CommitEntry> = serde_json::from_slice(&body)
+            .with_context(|| format!("Invalid response from {}", url))?;
+        if commits.is_empty() {
+            return Ok(None);
+        }
+
+        let found = commits.into_iter().find(|entry| {
+            let subject = entry.commit.message.lines().next().unwrap_or_default();
+            subject.starts_with(&bottle)
+                || subject == bump
+                || subject.starts_with(&format!("{} ", bump))
+        });
+        if let Some(entry) = found {
+            return Ok(Some(entry.sha));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Renames the formula class to match `name@version` and drops the bottle block, since
+/// core bottles can't be poured from another tap.
+fn rewrite_formula(source: &str, versioned_name: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_bottle = false;
+
+    for line in source.lines() {
+        if in_bottle {
+            in_bottle = line != "  end";
+            continue;
+        }
+        if line == "  bottle do" {
+            in_bottle = true;
+            continue;
+        }
+
+        match line
+            .strip_prefix("class ")
+            .and_then(|rest| rest.split_once(" < "))
+        {
+            Some((_, parent)) => {
+                lines.push(format!("class {} < {}", class_name(versioned_name), parent))
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Homebrew's formula class naming, e.g. `terraform@1.5.7` becomes `TerraformAT157`.
+fn class_name(versioned_name: &str) -> String {
+    let mut class = String::new();
+    let mut chars = versioned_name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        match c {
+            '-' | '_' | '.' | ' ' if next.is_some_and(|n| n.is_ascii_alphanumeric()) => {
+                class.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+            }
+            '+' => class.push('x'),
+            '@' if next.is_some_and(|n| n.is_ascii_digit()) => class.push_str("AT"),
+            _ if class.is_empty() => class.extend(c.to_uppercase()),
+            _ => class.extend(c.to_lowercase()),
+        }
+    }
+
+    class
+}
@@ -0,0 +1,320 @@
+//! Metadata lookups and self-update go to the base URLs set in the environment.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::thread;
+
+/// Serves `routes` (path → JSON body) on a local port, answering 404 for anything else.
+/// Returns the server's base URL.
+fn serve(routes: &[(&str, String)]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let routes = routes
+        .iter()
+        .map(|(path, body)| (path.to_string(), body.clone()))
+        .collect::<HashMap<_, _>>();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).ok();
+            // Skip the headers up to the blank line
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+            let response = match routes.get(path) {
+                Some(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            stream.write_all(response.as_bytes()).ok();
+        }
+    });
+
+    url
+}
+
+/// Answers the first request with `body`, and every later one with 503 like an API that
+/// went down in the meantime.
+fn serve_once(body: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mut body = Some(body.to_string());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request_line = String::new();
+            BufReader::new(&stream).read_line(&mut request_line).ok();
+            let response = match body.take() {
+                Some(body) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                None => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            stream.write_all(response.as_bytes()).ok();
+        }
+    });
+
+    url
+}
+
+/// A scratch HOME so the config and HTTP cache start out empty.
+fn scratch_home(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bert-mirror-{}-{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_bert(home: &PathBuf, url: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bert"))
+        .args(args)
+        .env("HOME", home)
+        .env("BERT_HOMEBREW_API_URL", url)
+        .env("BERT_NPM_REGISTRY_URL", url)
+        .env("BERT_GITHUB_API_URL", url)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn looks_up_formulae_from_the_homebrew_mirror() {
+    let url = serve(&[(
+        "/formula/jq.json",
+        r#"{"name":"jq","full_name":"jq","desc":"JSON processor","homepage":null,"versions":{"stable":"9.9.9"},"tap":"homebrew/core","license":null}"#
+            .to_string(),
+    )]);
+    let home = scratch_home("formula");
+    let output = run_bert(
+        &home,
+        &url,
+        &["--dry-run", "install", "--output", "json", "jq"],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains(r#""version": "9.9.9""#), "{}", stdout);
+    assert!(stdout.contains("JSON processor"), "{}", stdout);
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn looks_up_node_packages_from_the_npm_mirror() {
+    let url = serve(&[(
+        "/typescript",
+        r#"{"name":"typescript","description":"Typed JavaScript","dist-tags":{"latest":"9.8.7"},"versions":{"9.8.7":{}}}"#
+            .to_string(),
+    )]);
+    let home = scratch_home("node");
+    let output = run_bert(
+        &home,
+        &url,
+        &[
+            "--dry-run",
+            "--node",
+            "install",
+            "--output",
+            "json",
+            "typescript",
+        ],
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout.contains(r#""version": "9.8.7""#), "{}", stdout);
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn reports_packages_missing_from_the_mirror() {
+    let url = serve(&[]);
+    let home = scratch_home("missing");
+    let output = run_bert(
+        &home,
+        &url,
+        &[
+            "--dry-run",
+            "install",
+            "--output",
+            "json",
+            "no-such-formula",
+        ],
+    );
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not found"));
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn self_update_checks_the_github_mirror() {
+    let release = format!(
+        r#"{{"tag_name":"v{}","body":null,"assets":[],"html_url":"https://example.com"}}"#,
+        env!("CARGO_PKG_VERSION")
+    );
+    let url = serve(&[("/repos/michaelessiet/bert-cli/releases/latest", release)]);
+    let home = scratch_home("self-update");
+    let output = run_bert(&home, &url, &["self-update"]);
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("already up to date"));
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn self_update_refuses_a_release_without_a_matching_binary() {
+    let release = r#"{"tag_name":"v999.0.0","body":null,"assets":[{"name":"bert-other","browser_download_url":"http://127.0.0.1:9/bert"}],"html_url":"https://example.com"}"#;
+    let url = serve(&[(
+        "/repos/michaelessiet/bert-cli/releases/latest",
+        release.to_string(),
+    )]);
+    let home = scratch_home("no-asset");
+    let output = run_bert(&home, &url, &["self-update"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("999.0.0"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No compatible binary"));
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn backend_flags_apply_to_the_packages_after_them() {
+    let url = serve(&[
+        (
+            "/formula/jq.json",
+            r#"{"name":"jq","full_name":"jq","desc":null,"homepage":null,"versions":{"stable":"1.7.1"},"tap":"homebrew/core","license":null}"#
+                .to_string(),
+        ),
+        (
+            "/typescript",
+            r#"{"name":"typescript","dist-tags":{"latest":"5.3.3"},"versions":{"5.3.3":{}}}"#
+                .to_string(),
+        ),
+    ]);
+    let home = scratch_home("flags");
+
+    let mixed = run_bert(
+        &home,
+        &url,
+        &[
+            "-y",
+            "--dry-run",
+            "install",
+            "--output",
+            "json",
+            "jq",
+            "--node",
+            "typescript",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&mixed.stdout);
+    assert!(
+        mixed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&mixed.stderr)
+    );
+    assert!(stdout.contains(r#""type": "formula""#), "{}", stdout);
+    assert!(stdout.contains(r#""type": "node""#), "{}", stdout);
+
+    // A flag before the subcommand covers every package, even after short flags
+    let leading = run_bert(
+        &home,
+        &url,
+        &[
+            "-y",
+            "--node",
+            "--dry-run",
+            "install",
+            "--output",
+            "json",
+            "typescript",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&leading.stdout);
+    assert!(
+        leading.status.success(),
+        "{}",
+        String::from_utf8_lossy(&leading.stderr)
+    );
+    assert!(stdout.contains(r#""type": "node""#), "{}", stdout);
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn keys_ephemeral_node_prefixes_by_resolved_version() {
+    let url = serve(&[(
+        "/cowsay",
+        r#"{"name":"cowsay","dist-tags":{"latest":"1.6.0"},"versions":{"1.5.0":{},"1.6.0":{}}}"#
+            .to_string(),
+    )]);
+    let home = scratch_home("run");
+
+    for (spec, prefix) in [("cowsay", "cowsay@1.6.0"), ("cowsay@~1.5", "cowsay@1.5.0")] {
+        let output = run_bert(&home, &url, &["--dry-run", "run", "--node", spec]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(
+            stdout.contains(&format!("/node/{}/bin/cowsay", prefix)),
+            "{}",
+            stdout
+        );
+    }
+    fs::remove_dir_all(home).ok();
+}
+
+#[test]
+fn falls_back_to_stale_cache_when_the_api_fails() {
+    let url = serve_once(
+        r#"{"name":"jq","full_name":"jq","desc":"JSON processor","homepage":null,"versions":{"stable":"9.9.9"},"tap":"homebrew/core","license":null}"#,
+    );
+    let home = scratch_home("stale");
+    // Every cached entry is stale right away
+    fs::create_dir_all(home.join(".bert")).unwrap();
+    fs::write(
+        home.join(".bert").join("config.json"),
+        r#"{"node_package_manager":"npm","cache_ttl_secs":0}"#,
+    )
+    .unwrap();
+    let args = ["--dry-run", "install", "--output", "json", "jq"];
+    assert!(run_bert(&home, &url, &args).status.success());
+
+    let output = run_bert(&home, &url, &args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("9.9.9"));
+    assert!(stderr.contains("using cached data"), "{}", stderr);
+    fs::remove_dir_all(home).ok();
+}
@@ -0,0 +1,69 @@
+//! `bert <command>` exits with the wrapped command's exit code.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A scratch HOME with a stub command on PATH that exits as told by its first argument.
+fn setup(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bert-{}-{}", name, std::process::id()));
+    let bin = dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+
+    let stub = bin.join("bert-stub");
+    fs::write(
+        &stub,
+        "#!/bin/sh\ncase \"$1\" in\n  exit) exit 7 ;;\n  term) kill -TERM $$ ;;\nesac\nexit 0\n",
+    )
+    .unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+    dir
+}
+
+fn run_stub(dir: &PathBuf, arg: &str) -> Output {
+    let path = format!(
+        "{}:{}",
+        dir.join("bin").display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    Command::new(env!("CARGO_BIN_EXE_bert"))
+        .args(["bert-stub", arg])
+        .env("HOME", dir)
+        .env("PATH", path)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn exits_with_the_commands_exit_code() {
+    let dir = setup("exit");
+    let output = run_stub(&dir, "exit");
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Failed to execute command"));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn dies_from_the_signal_that_killed_the_command() {
+    let dir = setup("term");
+    let output = run_stub(&dir, "term");
+
+    // SIGTERM is 15
+    assert_eq!(output.status.signal(), Some(15));
+    assert_eq!(output.status.code(), None);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Failed to execute command"));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn exits_zero_when_the_command_succeeds() {
+    let dir = setup("ok");
+    let output = run_stub(&dir, "ok");
+
+    assert_eq!(output.status.code(), Some(0));
+    fs::remove_dir_all(dir).ok();
+}
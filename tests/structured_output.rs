@@ -0,0 +1,53 @@
+//! `--output json` keeps stdout parseable, with everything else on stderr.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+#[test]
+fn dry_run_tap_goes_to_stderr() {
+    let home = std::env::temp_dir().join(format!("bert-structured-{}", std::process::id()));
+    let bin = home.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+
+    // A stub brew that knows a single tapped formula
+    let brew = bin.join("brew");
+    fs::write(
+        &brew,
+        r#"#!/bin/sh
+if [ "$1" = "info" ]; then
+  echo '{"formulae":[{"name":"bun","full_name":"oven-sh/bun/bun","desc":"Bun","homepage":null,"versions":{"stable":"1.1.8"},"tap":"oven-sh/bun","license":null}],"casks":[]}'
+fi
+exit 0
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&brew, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = format!(
+        "{}:{}",
+        bin.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_bert"))
+        .args([
+            "--dry-run",
+            "--yes",
+            "install",
+            "--output",
+            "json",
+            "oven-sh/bun/bun",
+        ])
+        .env("HOME", &home)
+        .env("PATH", path)
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(records[0]["version"], "1.1.8");
+    assert!(stderr.contains("brew tap oven-sh/bun"), "{}", stderr);
+    fs::remove_dir_all(home).ok();
+}
@@ -0,0 +1,31 @@
+//! `bert undo` reports updates it can't revert instead of claiming success.
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn refuses_to_undo_a_cask_update() {
+    let home = std::env::temp_dir().join(format!("bert-undo-{}", std::process::id()));
+    fs::create_dir_all(home.join(".bert")).unwrap();
+    fs::write(
+        home.join(".bert").join("history.jsonl"),
+        r#"{"id":1,"timestamp":"2026-01-01T00:00:00+00:00","action":"update","changes":[{"backend":"cask","package":"firefox","before":"120.0","after":"121.0"}]}"#,
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bert"))
+        .args(["--dry-run", "undo"])
+        .env("HOME", &home)
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(
+        stdout.contains("firefox can't be reverted to 120.0"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Undo completed"), "{}", stdout);
+    fs::remove_dir_all(home).ok();
+}